@@ -0,0 +1,117 @@
+//! API latency while the RPC provider is slow.
+//!
+//! Boots the real binary against the local JSON-RPC stand-in from `tests/support`, with
+//! every RPC answer delayed, then hammers a few HTTP routes and reports latency
+//! percentiles. With the engine on the nonblocking client, a 3s RPC round trip must not
+//! show up in handler latency; the run fails if p95 of any route exceeds `MAX_P95` with
+//! the slow provider.
+//!
+//!     cargo bench --bench slow_rpc
+
+#[path = "../tests/support/mod.rs"]
+mod support;
+
+use std::time::{Duration, Instant};
+use support::standin::{Scenario, StandIn};
+use support::App;
+
+const MAX_P95: Duration = Duration::from_millis(250);
+const RUN_FOR: Duration = Duration::from_secs(10);
+const CONCURRENCY: usize = 16;
+
+#[tokio::main]
+async fn main() {
+    let fast = run_scenario(Duration::ZERO).await;
+    let slow = run_scenario(Duration::from_secs(3)).await;
+
+    println!("{:<14} {:>8} {:>10} {:>10} {:>10}", "route", "rpc", "p50", "p95", "max");
+    for (label, results) in [("0ms", &fast), ("3000ms", &slow)] {
+        for r in results {
+            println!("{:<14} {:>8} {:>10.1?} {:>10.1?} {:>10.1?}", r.route, label, r.p50, r.p95, r.max);
+        }
+    }
+
+    let failed: Vec<&RouteLatency> = slow.iter().filter(|r| r.p95 > MAX_P95).collect();
+    if !failed.is_empty() {
+        for r in failed {
+            eprintln!("{}: p95 {:?} above {:?} while the RPC is slow", r.route, r.p95, MAX_P95);
+        }
+        std::process::exit(1);
+    }
+}
+
+struct RouteLatency {
+    route: &'static str,
+    p50: Duration,
+    p95: Duration,
+    max: Duration,
+}
+
+async fn run_scenario(rpc_delay: Duration) -> Vec<RouteLatency> {
+    let node = StandIn::start(Scenario::Steady).await;
+    node.set_delay(rpc_delay);
+    let app = App::start_with(&[node.url()], &[("ARKHEION_RPC_TIMEOUT_MS", "10000"), ("ARKHEION_POLL_INTERVAL_MS", "100")]).await;
+    let (client, base) = (app.client.clone(), app.url(""));
+
+    // `/` and `/metrics` sit outside the rate limiter and take the full load; `/api/metrics`
+    // is paced to stay inside the anonymous quota (2 req/s).
+    let unlimited = ["/", "/metrics"].map(|route| {
+        let (client, base) = (client.clone(), base.clone());
+        tokio::spawn(async move { (route, hammer(&client, &base, route, CONCURRENCY, None).await) })
+    });
+    let paced = {
+        let (client, base) = (client.clone(), base.clone());
+        tokio::spawn(async move {
+            ("/api/metrics", hammer(&client, &base, "/api/metrics", 1, Some(Duration::from_millis(550))).await)
+        })
+    };
+
+    let mut results = Vec::new();
+    for handle in unlimited.into_iter().chain([paced]) {
+        let (route, mut samples) = handle.await.unwrap();
+        samples.sort();
+        results.push(RouteLatency {
+            route,
+            p50: percentile(&samples, 0.50),
+            p95: percentile(&samples, 0.95),
+            max: *samples.last().unwrap(),
+        });
+    }
+    results
+}
+
+/// `workers` loops requesting `route` until `RUN_FOR` has passed, optionally sleeping
+/// `pace` between requests. Returns every latency observed.
+async fn hammer(client: &reqwest::Client, base: &str, route: &str, workers: usize, pace: Option<Duration>) -> Vec<Duration> {
+    let deadline = Instant::now() + RUN_FOR;
+    let tasks: Vec<_> = (0..workers)
+        .map(|_| {
+            let (client, url) = (client.clone(), format!("{}{}", base, route));
+            tokio::spawn(async move {
+                let mut samples = Vec::new();
+                while Instant::now() < deadline {
+                    let start = Instant::now();
+                    let res = client.get(&url).send().await.expect("request failed");
+                    assert!(res.status().is_success(), "{} answered {}", url, res.status());
+                    res.bytes().await.unwrap();
+                    samples.push(start.elapsed());
+                    if let Some(pace) = pace {
+                        tokio::time::sleep(pace).await;
+                    }
+                }
+                samples
+            })
+        })
+        .collect();
+
+    let mut all = Vec::new();
+    for task in tasks {
+        all.extend(task.await.unwrap());
+    }
+    all
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = ((sorted.len() as f64 * p).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
@@ -0,0 +1,86 @@
+-- Baseline: the schema as it stood before versioned migrations.
+-- IF NOT EXISTS so databases created by the old init_db adopt it unchanged.
+
+-- Wallet users (SIWS) have public_key, console users registered by email have email.
+-- users.api_key holds `<key_id>:<hmac-sha256 hex>`, never the key itself (see keys.rs).
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    public_key TEXT UNIQUE,
+    email TEXT UNIQUE,
+    api_key TEXT UNIQUE NOT NULL,
+    tier TEXT DEFAULT 'free',
+    credits INTEGER DEFAULT 1000,
+    requests INTEGER DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_active DATETIME DEFAULT CURRENT_TIMESTAMP,
+    CHECK (public_key IS NOT NULL OR email IS NOT NULL)
+);
+
+CREATE TABLE IF NOT EXISTS payment_tx (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    signature TEXT UNIQUE NOT NULL,
+    amount_sol REAL NOT NULL,
+    plan TEXT NOT NULL,
+    status TEXT DEFAULT 'pending',
+    failure_reason TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_check_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+-- Every payment_tx status change, so support can explain any balance.
+CREATE TABLE IF NOT EXISTS payment_audit (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    payment_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    from_status TEXT,
+    to_status TEXT NOT NULL,
+    reason TEXT,
+    actor TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(payment_id) REFERENCES payment_tx(id)
+);
+
+-- Named, scoped keys per user. key_hash holds `<key_id>:<hmac-sha256 hex>`.
+CREATE TABLE IF NOT EXISTS api_keys (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    key_id TEXT UNIQUE NOT NULL,
+    key_hash TEXT NOT NULL,
+    label TEXT NOT NULL DEFAULT 'default',
+    scopes TEXT NOT NULL DEFAULT 'metrics:read stream:read',
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_used_at DATETIME,
+    expires_at DATETIME,
+    revoked INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY(user_id) REFERENCES users(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_user ON api_keys(user_id);
+
+CREATE TABLE IF NOT EXISTS auth_nonces (
+    nonce TEXT PRIMARY KEY,
+    public_key TEXT NOT NULL,
+    message TEXT NOT NULL,
+    expires_at DATETIME NOT NULL,
+    used_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- One row per metered API call; cost is 0 when the call failed and was refunded.
+CREATE TABLE IF NOT EXISTS usage_ledger (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    api_key_id INTEGER,
+    endpoint TEXT NOT NULL,
+    cost INTEGER NOT NULL,
+    status_code INTEGER NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id),
+    FOREIGN KEY(api_key_id) REFERENCES api_keys(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_usage_ledger_user_time ON usage_ledger(user_id, created_at);
@@ -0,0 +1,36 @@
+-- Engine snapshots, one row per recorder tick. Kept for the raw retention window only.
+CREATE TABLE metric_samples (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sampled_at DATETIME NOT NULL,
+    slot INTEGER NOT NULL,
+    epoch INTEGER NOT NULL,
+    tps INTEGER,
+    latency_ms INTEGER NOT NULL,
+    slot_lag INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL
+);
+
+CREATE INDEX idx_metric_samples_time ON metric_samples(sampled_at);
+
+-- Aggregates of metric_samples per 1m / 1h / 1d bucket, computed from raw samples so
+-- p95 is exact. tps columns are NULL when no sample in the bucket had a TPS figure.
+CREATE TABLE metric_rollups (
+    resolution TEXT NOT NULL CHECK (resolution IN ('1m', '1h', '1d')),
+    bucket_start DATETIME NOT NULL,
+    samples INTEGER NOT NULL,
+    slot_min INTEGER NOT NULL,
+    slot_max INTEGER NOT NULL,
+    epoch INTEGER NOT NULL,
+    latency_min REAL NOT NULL,
+    latency_max REAL NOT NULL,
+    latency_avg REAL NOT NULL,
+    latency_p95 REAL NOT NULL,
+    tps_min REAL,
+    tps_max REAL,
+    tps_avg REAL,
+    tps_p95 REAL,
+    -- Status at the end of the bucket, and how many times it changed inside it.
+    status TEXT NOT NULL,
+    status_changes INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (resolution, bucket_start)
+);
@@ -0,0 +1,266 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, State},
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use crate::config;
+use crate::models::{AppState, User, UserProfile};
+
+const NONCE_TTL_MINUTES: i64 = 5;
+const SESSION_TTL_HOURS: i64 = 24;
+pub const SESSION_COOKIE: &str = "arkheion_session";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Server-side secret for signing sessions. Set `secret` (`ARKHEION_SECRET`) in production;
+/// without it a random per-process secret is used and sessions die on restart.
+pub fn server_secret() -> &'static [u8] {
+    static SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+    SECRET.get_or_init(|| match &config::get().secret {
+        Some(s) => s.clone().into_bytes(),
+        None => {
+            tracing::warn!("ARKHEION_SECRET not set, using an ephemeral session secret");
+            random_bytes(32)
+        }
+    })
+}
+
+pub fn random_bytes(len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut buf);
+    buf
+}
+
+fn error(status: StatusCode, msg: &str) -> Response {
+    (status, Json(json!({ "error": msg }))).into_response()
+}
+
+/// Sign-In With Solana style message. The exact text is stored with the nonce so the
+/// verify step checks the signature against what the wallet was actually shown.
+/// `domain` is always the configured `siws_domain`, never the request's `Host`, so a
+/// phishing site cannot obtain a challenge that names itself.
+fn siws_message(domain: &str, public_key: &str, nonce: &str, issued_at: &str, expires_at: &str) -> String {
+    format!(
+        "{domain} wants you to sign in with your Solana account:\n\
+         {public_key}\n\n\
+         Sign in to the ARKHEIONX console.\n\n\
+         URI: https://{domain}\n\
+         Version: 1\n\
+         Nonce: {nonce}\n\
+         Issued At: {issued_at}\n\
+         Expiration Time: {expires_at}"
+    )
+}
+
+#[derive(Deserialize)]
+pub struct NonceRequest {
+    pub public_key: String,
+}
+
+pub async fn issue_nonce(State(state): State<Arc<AppState>>, Json(req): Json<NonceRequest>) -> Response {
+    if Pubkey::from_str(&req.public_key).is_err() {
+        return error(StatusCode::BAD_REQUEST, "Invalid public key");
+    }
+
+    // Housekeeping: expired challenges are never useful again.
+    let _ = sqlx::query("DELETE FROM auth_nonces WHERE expires_at < ?")
+        .bind(Utc::now())
+        .execute(&state.db)
+        .await;
+
+    let nonce = bs58::encode(random_bytes(16)).into_string();
+    let issued_at = Utc::now();
+    let expires_at = issued_at + Duration::minutes(NONCE_TTL_MINUTES);
+    let message = siws_message(&config::get().siws_domain, &req.public_key, &nonce, &issued_at.to_rfc3339(), &expires_at.to_rfc3339());
+
+    let res = sqlx::query(
+        "INSERT INTO auth_nonces (nonce, public_key, message, expires_at) VALUES (?, ?, ?, ?)",
+    )
+    .bind(&nonce)
+    .bind(&req.public_key)
+    .bind(&message)
+    .bind(expires_at)
+    .execute(&state.db)
+    .await;
+
+    if let Err(e) = res {
+        tracing::error!(error = %e, "issue_nonce failed");
+        return error(StatusCode::INTERNAL_SERVER_ERROR, "Could not issue nonce");
+    }
+
+    Json(json!({
+        "nonce": nonce,
+        "message": message,
+        "expires_at": expires_at.to_rfc3339()
+    }))
+    .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct VerifyRequest {
+    pub public_key: String,
+    pub nonce: String,
+    /// Base58 ed25519 signature over the `message` returned by the nonce endpoint.
+    pub signature: String,
+}
+
+pub async fn verify_signature(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<VerifyRequest>,
+) -> Response {
+    let (Ok(pubkey), Ok(signature)) = (Pubkey::from_str(&req.public_key), Signature::from_str(&req.signature)) else {
+        return error(StatusCode::BAD_REQUEST, "Malformed public key or signature");
+    };
+
+    let row: Option<(String,)> = match sqlx::query_as(
+        "SELECT message FROM auth_nonces
+         WHERE nonce = ? AND public_key = ? AND used_at IS NULL AND expires_at > ?",
+    )
+    .bind(&req.nonce)
+    .bind(&req.public_key)
+    .bind(Utc::now())
+    .fetch_optional(&state.db)
+    .await
+    {
+        Ok(row) => row,
+        Err(e) => {
+            tracing::error!(error = %e, "verify_signature failed");
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Could not verify signature");
+        }
+    };
+
+    let Some((message,)) = row else {
+        return error(StatusCode::UNAUTHORIZED, "Unknown, expired or already used nonce");
+    };
+
+    // Challenges issued before a domain change are no longer valid for this site.
+    let domain = &config::get().siws_domain;
+    if message.split_once(" wants you to sign in").map(|(d, _)| d) != Some(domain.as_str()) {
+        return error(StatusCode::UNAUTHORIZED, "Sign-in message is for another domain");
+    }
+
+    if !signature.verify(pubkey.as_ref(), message.as_bytes()) {
+        return error(StatusCode::UNAUTHORIZED, "Signature verification failed");
+    }
+
+    // Consume the nonce; a concurrent request with the same signature loses this race.
+    let consumed = sqlx::query("UPDATE auth_nonces SET used_at = CURRENT_TIMESTAMP WHERE nonce = ? AND used_at IS NULL")
+        .bind(&req.nonce)
+        .execute(&state.db)
+        .await
+        .map(|r| r.rows_affected() == 1)
+        .unwrap_or(false);
+    if !consumed {
+        return error(StatusCode::UNAUTHORIZED, "Nonce already used");
+    }
+
+    let user = match upsert_user(&state, &req.public_key).await {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!(error = %e, "upsert_user failed");
+            return error(StatusCode::INTERNAL_SERVER_ERROR, "Could not create user");
+        }
+    };
+
+    let token = sign_session(user.id, Utc::now().timestamp() + SESSION_TTL_HOURS * 3600);
+    let cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}{}",
+        SESSION_COOKIE,
+        token,
+        SESSION_TTL_HOURS * 3600,
+        if config::get().secure_cookies { "; Secure" } else { "" }
+    );
+
+    let profile = UserProfile {
+        wallet: user.public_key.unwrap_or_default(),
+        tier: user.tier,
+        usage_limit: format!("{} credits", user.credits),
+        status: "active".to_string(),
+    };
+
+    ([(header::SET_COOKIE, cookie)], Json(json!({ "user": profile, "session": token }))).into_response()
+}
+
+/// Creates the `users` row on first sign-in, otherwise just bumps `last_active`.
+async fn upsert_user(state: &AppState, public_key: &str) -> Result<User, sqlx::Error> {
+    // api_key is NOT NULL UNIQUE; new wallets get a hash of a key nobody ever sees
+    // until they mint a real one.
+    let placeholder = crate::keys::mint().stored;
+
+    sqlx::query(
+        "INSERT INTO users (public_key, api_key) VALUES (?, ?)
+         ON CONFLICT(public_key) DO UPDATE SET last_active = CURRENT_TIMESTAMP",
+    )
+    .bind(public_key)
+    .bind(&placeholder)
+    .execute(&state.db)
+    .await?;
+
+    sqlx::query_as("SELECT * FROM users WHERE public_key = ?")
+        .bind(public_key)
+        .fetch_one(&state.db)
+        .await
+}
+
+fn session_mac(payload: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(server_secret()).expect("HMAC accepts any key length");
+    mac.update(payload.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Session tokens are `<user_id>.<expires_unix>.<base58 hmac>`, so no server-side table is needed.
+pub fn sign_session(user_id: i64, expires_unix: i64) -> String {
+    let payload = format!("{}.{}", user_id, expires_unix);
+    format!("{}.{}", payload, bs58::encode(session_mac(&payload)).into_string())
+}
+
+pub fn verify_session(token: &str) -> Option<i64> {
+    let (payload, sig) = token.rsplit_once('.')?;
+    let (user_id, expires) = payload.split_once('.')?;
+    let sig = bs58::decode(sig).into_vec().ok()?;
+
+    let mut mac = HmacSha256::new_from_slice(server_secret()).ok()?;
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&sig).ok()?;
+
+    if expires.parse::<i64>().ok()? < Utc::now().timestamp() {
+        return None;
+    }
+    user_id.parse().ok()
+}
+
+/// Extractor for routes that require a signed-in wallet (cookie or `Authorization: Session <token>`).
+pub struct Session {
+    pub user_id: i64,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Session {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let from_header = parts.headers.get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Session "));
+
+        let from_cookie = parts.headers.get_all(header::COOKIE).iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(';'))
+            .find_map(|c| c.trim().strip_prefix(SESSION_COOKIE)?.strip_prefix('='));
+
+        from_header.or(from_cookie)
+            .and_then(verify_session)
+            .map(|user_id| Session { user_id })
+            .ok_or_else(|| error(StatusCode::UNAUTHORIZED, "Not signed in"))
+    }
+}
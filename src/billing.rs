@@ -0,0 +1,551 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, TransactionConfirmationStatus, UiInstruction,
+    UiMessage, UiParsedInstruction,
+};
+use sqlx::{Pool, Sqlite};
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::auth::Session;
+use crate::config;
+use crate::models::{AppState, PaymentTx};
+use crate::rpc_source::RpcSource;
+
+pub struct Plan {
+    pub id: &'static str,
+    pub lamports: u64,
+    pub credits: i64,
+    pub tier: Option<&'static str>,
+}
+
+pub const PLANS: &[Plan] = &[
+    Plan { id: "credits_10k", lamports: LAMPORTS_PER_SOL / 20, credits: 10_000, tier: None },
+    Plan { id: "credits_100k", lamports: LAMPORTS_PER_SOL / 4, credits: 100_000, tier: None },
+    Plan { id: "pro", lamports: LAMPORTS_PER_SOL, credits: 250_000, tier: Some("pro") },
+    Plan { id: "enterprise", lamports: 5 * LAMPORTS_PER_SOL, credits: 2_000_000, tier: Some("enterprise") },
+];
+
+pub fn plan(id: &str) -> Option<&'static Plan> {
+    PLANS.iter().find(|p| p.id == id)
+}
+
+/// Wallet that receives payments, from `treasury` (`ARKHEION_TREASURY`). Validated at startup.
+pub fn treasury() -> Option<Pubkey> {
+    config::get().treasury.as_deref().and_then(|s| Pubkey::from_str(s).ok())
+}
+
+/// Memo the payer must attach so a transfer can't be claimed by another account or plan.
+pub fn expected_memo(user_id: i64, plan_id: &str) -> String {
+    format!("arkheion:{}:{}", user_id, plan_id)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Verification {
+    Confirmed,
+    /// Not visible or not finalized yet; worth checking again later.
+    Pending,
+    Failed(String),
+}
+
+/// Checks a finalized transaction moves at least `lamports` from `payer` to `treasury`
+/// and carries `memo`. Pure so it can be exercised without an RPC node.
+pub fn verify_transfer(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+    payer: &Pubkey,
+    treasury: &Pubkey,
+    lamports: u64,
+    memo: &str,
+) -> Verification {
+    if let Some(meta) = &tx.transaction.meta {
+        if let Some(err) = &meta.err {
+            return Verification::Failed(format!("transaction failed on-chain: {}", err));
+        }
+    }
+
+    let EncodedTransaction::Json(ui) = &tx.transaction.transaction else {
+        return Verification::Failed("unexpected transaction encoding".to_string());
+    };
+    let UiMessage::Parsed(message) = &ui.message else {
+        return Verification::Failed("unexpected message encoding".to_string());
+    };
+
+    let (payer, treasury) = (payer.to_string(), treasury.to_string());
+    let mut paid: u64 = 0;
+    let mut memo_found = false;
+
+    for ix in &message.instructions {
+        let UiInstruction::Parsed(UiParsedInstruction::Parsed(ix)) = ix else { continue };
+        match ix.program.as_str() {
+            "system" => {
+                let info = &ix.parsed["info"];
+                if ix.parsed["type"] == "transfer"
+                    && info["source"] == Value::from(payer.as_str())
+                    && info["destination"] == Value::from(treasury.as_str())
+                {
+                    paid = paid.saturating_add(info["lamports"].as_u64().unwrap_or(0));
+                }
+            }
+            "spl-memo" => memo_found |= ix.parsed.as_str().is_some_and(|m| m.trim() == memo),
+            _ => {}
+        }
+    }
+
+    if !memo_found {
+        return Verification::Failed(format!("missing memo \"{}\"", memo));
+    }
+    if paid < lamports {
+        return Verification::Failed(format!("transferred {} lamports, expected {}", paid, lamports));
+    }
+    Verification::Confirmed
+}
+
+/// Looks the signature up on chain and verifies it against the payment row.
+pub async fn check_payment(rpc: &dyn RpcSource, payment: &PaymentTx, payer: &Pubkey) -> Verification {
+    let Some(treasury) = treasury() else {
+        return Verification::Pending;
+    };
+    verify_payment(rpc, payment, payer, &treasury).await
+}
+
+/// [`check_payment`] against an explicit treasury.
+pub async fn verify_payment(rpc: &dyn RpcSource, payment: &PaymentTx, payer: &Pubkey, treasury: &Pubkey) -> Verification {
+    let Some(plan) = plan(&payment.plan) else {
+        return Verification::Failed(format!("unknown plan {}", payment.plan));
+    };
+    let Ok(signature) = Signature::from_str(&payment.signature) else {
+        return Verification::Failed("malformed signature".to_string());
+    };
+
+    let status = match rpc.get_signature_status(signature).await {
+        Ok(status) => status,
+        Err(e) => {
+            tracing::warn!(error = %e, "getSignatureStatuses failed");
+            return Verification::Pending;
+        }
+    };
+    let Some(status) = status else {
+        return Verification::Pending;
+    };
+    if let Some(err) = status.err {
+        return Verification::Failed(format!("transaction failed on-chain: {}", err));
+    }
+    if status.confirmation_status != Some(TransactionConfirmationStatus::Finalized) && status.confirmations.is_some() {
+        return Verification::Pending;
+    }
+
+    match rpc.get_transaction(signature).await {
+        Ok(tx) => verify_transfer(&tx, payer, treasury, plan.lamports, &expected_memo(payment.user_id, plan.id)),
+        Err(e) => {
+            tracing::warn!(error = %e, "getTransaction failed");
+            Verification::Pending
+        }
+    }
+}
+
+/// Who moved a payment between states, recorded in `payment_audit`.
+#[derive(Debug, Clone, Copy)]
+pub enum Actor {
+    Api,
+    Reconciler,
+}
+
+impl Actor {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Actor::Api => "api",
+            Actor::Reconciler => "reconciler",
+        }
+    }
+}
+
+async fn audit<'e, E: sqlx::Executor<'e, Database = Sqlite>>(
+    ex: E,
+    payment: &PaymentTx,
+    from: Option<&str>,
+    to: &str,
+    reason: Option<&str>,
+    actor: Actor,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO payment_audit (payment_id, user_id, from_status, to_status, reason, actor)
+         VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(payment.id)
+    .bind(payment.user_id)
+    .bind(from)
+    .bind(to)
+    .bind(reason)
+    .bind(actor.as_str())
+    .execute(ex)
+    .await?;
+    Ok(())
+}
+
+/// Moves a pending payment to `confirmed` and credits the account, exactly once.
+pub async fn confirm_payment(db: &Pool<Sqlite>, payment: &PaymentTx, actor: Actor) -> Result<bool, sqlx::Error> {
+    let Some(plan) = plan(&payment.plan) else { return Ok(false) };
+    let mut tx = db.begin().await?;
+
+    let updated = sqlx::query("UPDATE payment_tx SET status = 'confirmed', updated_at = CURRENT_TIMESTAMP WHERE id = ? AND status = 'pending'")
+        .bind(payment.id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query("UPDATE users SET credits = credits + ?, tier = COALESCE(?, tier) WHERE id = ?")
+        .bind(plan.credits)
+        .bind(plan.tier)
+        .bind(payment.user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let reason = format!("+{} credits{}", plan.credits, plan.tier.map(|t| format!(", tier {}", t)).unwrap_or_default());
+    audit(&mut *tx, payment, Some("pending"), "confirmed", Some(&reason), actor).await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Ends a pending payment without crediting anything. `status` is `failed` or `expired`.
+pub async fn close_payment(db: &Pool<Sqlite>, payment: &PaymentTx, status: &str, reason: &str, actor: Actor) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+
+    let updated = sqlx::query("UPDATE payment_tx SET status = ?, failure_reason = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND status = 'pending'")
+        .bind(status)
+        .bind(reason)
+        .bind(payment.id)
+        .execute(&mut *tx)
+        .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    audit(&mut *tx, payment, Some("pending"), status, Some(reason), actor).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+fn error(status: StatusCode, msg: &str) -> Response {
+    (status, Json(json!({ "error": msg }))).into_response()
+}
+
+fn db_error(ctx: &str, e: sqlx::Error) -> Response {
+    tracing::error!(error = %e, "{} failed", ctx);
+    error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+}
+
+/// What to pay and how: treasury address, plans and the memo this account must use.
+pub async fn list_plans(session: Session) -> Response {
+    let plans: Vec<_> = PLANS.iter()
+        .map(|p| json!({
+            "id": p.id,
+            "amount_sol": p.lamports as f64 / LAMPORTS_PER_SOL as f64,
+            "lamports": p.lamports,
+            "credits": p.credits,
+            "tier": p.tier,
+            "memo": expected_memo(session.user_id, p.id)
+        }))
+        .collect();
+
+    Json(json!({ "treasury": treasury().map(|t| t.to_string()), "plans": plans })).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct SubmitPayment {
+    pub signature: String,
+    pub plan: String,
+}
+
+pub async fn submit_payment(
+    State(state): State<Arc<AppState>>,
+    session: Session,
+    Json(req): Json<SubmitPayment>,
+) -> Response {
+    if treasury().is_none() {
+        return error(StatusCode::SERVICE_UNAVAILABLE, "Payments are not configured");
+    }
+    let Some(plan) = plan(&req.plan) else {
+        return error(StatusCode::BAD_REQUEST, "Unknown plan");
+    };
+    if Signature::from_str(&req.signature).is_err() {
+        return error(StatusCode::BAD_REQUEST, "Malformed transaction signature");
+    }
+
+    let wallet: Option<String> = match sqlx::query_scalar("SELECT public_key FROM users WHERE id = ?")
+        .bind(session.user_id)
+        .fetch_optional(&state.db)
+        .await
+    {
+        Ok(w) => w,
+        Err(e) => return db_error("submit_payment", e),
+    };
+    let Some(payer) = wallet.and_then(|w| Pubkey::from_str(&w).ok()) else {
+        return error(StatusCode::BAD_REQUEST, "Account has no wallet to pay from");
+    };
+
+    // signature is UNIQUE: a transaction can only ever be claimed once, by anyone.
+    let inserted: Result<PaymentTx, _> = sqlx::query_as(
+        "INSERT INTO payment_tx (user_id, signature, amount_sol, plan) VALUES (?, ?, ?, ?)
+         ON CONFLICT(signature) DO NOTHING RETURNING *",
+    )
+    .bind(session.user_id)
+    .bind(&req.signature)
+    .bind(plan.lamports as f64 / LAMPORTS_PER_SOL as f64)
+    .bind(plan.id)
+    .fetch_one(&state.db)
+    .await;
+
+    let payment = match inserted {
+        Ok(p) => p,
+        Err(sqlx::Error::RowNotFound) => return error(StatusCode::CONFLICT, "Transaction signature already submitted"),
+        Err(e) => return db_error("submit_payment", e),
+    };
+    if let Err(e) = audit(&state.db, &payment, None, "pending", None, Actor::Api).await {
+        return db_error("submit_payment", e);
+    }
+
+    let outcome = check_payment(state.rpc.as_ref(), &payment, &payer).await;
+    let (status, body) = match outcome {
+        Verification::Confirmed => match confirm_payment(&state.db, &payment, Actor::Api).await {
+            Ok(_) => (StatusCode::OK, json!({ "status": "confirmed", "credits_added": plan.credits, "tier": plan.tier })),
+            Err(e) => return db_error("confirm_payment", e),
+        },
+        Verification::Pending => (StatusCode::ACCEPTED, json!({ "status": "pending" })),
+        Verification::Failed(reason) => {
+            if let Err(e) = close_payment(&state.db, &payment, "failed", &reason, Actor::Api).await {
+                return db_error("close_payment", e);
+            }
+            (StatusCode::UNPROCESSABLE_ENTITY, json!({ "status": "failed", "reason": reason }))
+        }
+    };
+
+    (status, Json(json!({ "payment_id": payment.id, "signature": payment.signature, "result": body }))).into_response()
+}
+
+/// Payment history with the full audit trail, newest first.
+pub async fn list_payments(State(state): State<Arc<AppState>>, session: Session) -> Response {
+    let payments: Vec<PaymentTx> = match sqlx::query_as("SELECT * FROM payment_tx WHERE user_id = ? ORDER BY created_at DESC")
+        .bind(session.user_id)
+        .fetch_all(&state.db)
+        .await
+    {
+        Ok(p) => p,
+        Err(e) => return db_error("list_payments", e),
+    };
+
+    let trail: Vec<(i64, Option<String>, String, Option<String>, String, String)> = match sqlx::query_as(
+        "SELECT payment_id, from_status, to_status, reason, actor, created_at FROM payment_audit
+         WHERE user_id = ? ORDER BY id",
+    )
+    .bind(session.user_id)
+    .fetch_all(&state.db)
+    .await
+    {
+        Ok(t) => t,
+        Err(e) => return db_error("list_payments", e),
+    };
+
+    let payments: Vec<_> = payments.into_iter()
+        .map(|p| {
+            let history: Vec<_> = trail.iter()
+                .filter(|t| t.0 == p.id)
+                .map(|(_, from, to, reason, actor, at)| json!({
+                    "from": from, "to": to, "reason": reason, "actor": actor, "at": at
+                }))
+                .collect();
+            json!({
+                "id": p.id,
+                "signature": p.signature,
+                "plan": p.plan,
+                "amount_sol": p.amount_sol,
+                "status": p.status,
+                "failure_reason": p.failure_reason,
+                "created_at": p.created_at.to_rfc3339(),
+                "history": history
+            })
+        })
+        .collect();
+
+    Json(json!({ "payments": payments })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_source::mock::ScriptedRpc;
+    use chrono::Utc;
+
+    struct Fixture {
+        rpc: ScriptedRpc,
+        payment: PaymentTx,
+        payer: Pubkey,
+        treasury: Pubkey,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let now = Utc::now();
+            let payment = PaymentTx {
+                id: 1,
+                user_id: 7,
+                signature: Signature::new_unique().to_string(),
+                amount_sol: 0.05,
+                plan: "credits_10k".to_string(),
+                status: "pending".to_string(),
+                failure_reason: None,
+                attempts: 0,
+                next_check_at: None,
+                created_at: now,
+                updated_at: now,
+            };
+            Self { rpc: ScriptedRpc::new("http://mock"), payment, payer: Pubkey::new_unique(), treasury: Pubkey::new_unique() }
+        }
+
+        fn status(&self, confirmation: &str, err: Option<&str>) -> &Self {
+            let status = match err {
+                Some(e) => json!({ "Err": e }),
+                None => json!({ "Ok": null }),
+            };
+            // Finalized statuses report no confirmation count.
+            let confirmations = if confirmation == "finalized" { Value::Null } else { json!(10) };
+            self.rpc.push("getSignatureStatuses", json!({
+                "context": { "slot": 1_000 },
+                "value": [{
+                    "slot": 990,
+                    "confirmations": confirmations,
+                    "status": status,
+                    "err": err,
+                    "confirmationStatus": confirmation,
+                }],
+            }));
+            self
+        }
+
+        /// A finalized jsonParsed transaction with one system transfer and one memo.
+        fn transaction(&self, lamports: u64, memo: &str) -> &Self {
+            let (payer, treasury) = (self.payer.to_string(), self.treasury.to_string());
+            self.rpc.push("getTransaction", json!({
+                "slot": 990,
+                "blockTime": 1_700_000_000,
+                "transaction": {
+                    "signatures": [self.payment.signature],
+                    "message": {
+                        "accountKeys": [
+                            { "pubkey": payer, "writable": true, "signer": true, "source": "transaction" },
+                            { "pubkey": treasury, "writable": true, "signer": false, "source": "transaction" },
+                        ],
+                        "recentBlockhash": "11111111111111111111111111111111",
+                        "instructions": [
+                            {
+                                "program": "system",
+                                "programId": "11111111111111111111111111111111",
+                                "parsed": { "type": "transfer", "info": { "source": payer, "destination": treasury, "lamports": lamports } },
+                            },
+                            {
+                                "program": "spl-memo",
+                                "programId": "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr",
+                                "parsed": memo,
+                            },
+                        ],
+                    },
+                },
+                "meta": null,
+            }));
+            self
+        }
+
+        async fn verify(&self) -> Verification {
+            verify_payment(&self.rpc, &self.payment, &self.payer, &self.treasury).await
+        }
+    }
+
+    const PRICE: u64 = LAMPORTS_PER_SOL / 20;
+
+    #[tokio::test]
+    async fn confirms_a_finalized_transfer() {
+        let f = Fixture::new();
+        f.status("finalized", None).transaction(PRICE, &expected_memo(7, "credits_10k"));
+        assert_eq!(f.verify().await, Verification::Confirmed);
+    }
+
+    #[tokio::test]
+    async fn waits_for_unknown_or_unfinalized_signatures() {
+        let f = Fixture::new();
+        f.rpc.push("getSignatureStatuses", json!({ "context": { "slot": 1_000 }, "value": [null] }));
+        assert_eq!(f.verify().await, Verification::Pending);
+
+        let f = Fixture::new();
+        f.status("confirmed", None);
+        assert_eq!(f.verify().await, Verification::Pending);
+        assert!(!f.rpc.calls().contains(&"getTransaction".to_string()));
+    }
+
+    #[tokio::test]
+    async fn rpc_errors_leave_the_payment_pending() {
+        let f = Fixture::new();
+        f.rpc.push_error("getSignatureStatuses", "connection reset");
+        assert_eq!(f.verify().await, Verification::Pending);
+
+        let f = Fixture::new();
+        f.status("finalized", None);
+        f.rpc.push_error("getTransaction", "timed out");
+        assert_eq!(f.verify().await, Verification::Pending);
+    }
+
+    #[tokio::test]
+    async fn rejects_failed_short_or_unmarked_transfers() {
+        let f = Fixture::new();
+        f.status("finalized", Some("AccountInUse"));
+        assert!(matches!(f.verify().await, Verification::Failed(m) if m.contains("failed on-chain")));
+
+        let f = Fixture::new();
+        f.status("finalized", None).transaction(PRICE - 1, &expected_memo(7, "credits_10k"));
+        assert!(matches!(f.verify().await, Verification::Failed(m) if m.starts_with("transferred")));
+
+        // Someone else's memo can't be used to claim this payment.
+        let f = Fixture::new();
+        f.status("finalized", None).transaction(PRICE, &expected_memo(8, "credits_10k"));
+        assert!(matches!(f.verify().await, Verification::Failed(m) if m.starts_with("missing memo")));
+    }
+
+    /// A credits_10k purchase by user 42 as a provider answered it, including the
+    /// "block not available" error nodes return just after finalization.
+    const RECORDED_PAYMENT: &str = include_str!("../tests/fixtures/rpc/payment_credits_10k.json");
+
+    #[tokio::test]
+    async fn replays_a_recorded_payment() {
+        let mut f = Fixture::new();
+        f.rpc.load_fixture(RECORDED_PAYMENT);
+        f.payment.user_id = 42;
+        f.payment.signature = "SEUKD9KgDCQuPMzRDsk5TNXkHAye6RLfBcpqUfZqeYRuGym7vJGCFCoMyTnhDkYYYxbRofmCJipwQ71f9Yt6uyx".to_string();
+        f.payer = Pubkey::from_str("6TmFrYXovKrj9eVt5TfpKHasxBWRVamyjdh5Yk2W9zsV").unwrap();
+        f.treasury = Pubkey::from_str("7GChTrP87ZYxcuMmjzXdKggvUfqhum8A3w5tXUqMMRwd").unwrap();
+
+        let epoch = f.rpc.get_epoch_info().await.unwrap();
+        assert_eq!((epoch.epoch, epoch.absolute_slot, epoch.slot_index, epoch.slots_in_epoch), (669, 289_123_456, 123_456, 432_000));
+
+        assert_eq!(f.verify().await, Verification::Pending);
+        assert_eq!(f.verify().await, Verification::Confirmed);
+
+        // Paid to someone else's treasury, the same transaction proves nothing.
+        f.treasury = Pubkey::new_unique();
+        assert!(matches!(f.verify().await, Verification::Failed(m) if m.starts_with("transferred 0")));
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_signatures_without_calling_rpc() {
+        let mut f = Fixture::new();
+        f.payment.signature = "not-a-signature".to_string();
+        assert_eq!(f.verify().await, Verification::Failed("malformed signature".to_string()));
+        assert!(f.rpc.calls().is_empty());
+    }
+}
@@ -0,0 +1,512 @@
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::Duration as ChronoDuration;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use crate::engine::{EngineConfig, StatusThresholds};
+use crate::history::{HistoryConfig, Resolution};
+use crate::reconciler::ReconcilerConfig;
+use crate::rpc_pool;
+use crate::telemetry::{self, LogFormat};
+
+pub const DEFAULT_CONFIG_FILE: &str = "arkheion.toml";
+const ENV_PREFIX: &str = "ARKHEION_";
+
+/// Every runtime setting. Sources, lowest to highest precedence: these defaults,
+/// `arkheion.toml` (or the file named by `ARKHEION_CONFIG`), `.env`, and `ARKHEION_*`
+/// environment variables. Each key's variable is its name upper-cased with the prefix,
+/// e.g. `rpc_urls` ← `ARKHEION_RPC_URLS` (comma-separated).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub host: IpAddr,
+    pub port: u16,
+    pub log_format: LogFormat,
+    /// `EnvFilter` directives for per-module levels, e.g. `info,arkheion_engine::rpc_pool=debug`.
+    pub log_filter: String,
+    /// Serve Prometheus `/metrics` on this separate (internal) address instead of the
+    /// public listener.
+    pub metrics_addr: Option<SocketAddr>,
+    pub database_url: String,
+    pub db_max_connections: u32,
+
+    /// In order of preference; see `rpc_pool`.
+    #[serde(serialize_with = "redact_urls")]
+    pub rpc_urls: Vec<String>,
+    /// Upper bound for a single RPC request before the pool tries the next endpoint.
+    pub rpc_timeout_ms: u64,
+    pub poll_interval_ms: u64,
+    pub probe_interval_secs: u64,
+
+    /// Engine status thresholds; see `engine::StatusThresholds`.
+    pub degraded_latency_ms: u64,
+    pub degraded_slot_lag: u64,
+    pub stall_after_secs: u64,
+    /// Share of skipped slots, 0 to 1.
+    pub degraded_skip_rate: f64,
+    pub degraded_slot_time_ms: u64,
+    pub reconnecting_after_errors: u32,
+    pub down_after_errors: u32,
+
+    pub history_sample_secs: u64,
+    pub history_rollup_secs: u64,
+    pub raw_retention_hours: i64,
+    pub minute_retention_days: i64,
+    pub hour_retention_days: i64,
+    pub day_retention_days: i64,
+
+    pub reconcile_interval_secs: u64,
+    pub payment_expiry_minutes: i64,
+    pub treasury: Option<String>,
+
+    /// HMAC key for API key hashes; required, at least 32 characters.
+    #[serde(serialize_with = "redact_secret")]
+    pub api_key_secret: Option<String>,
+    /// Domain (`host[:port]`) named in sign-in messages. Wallets compare it with the site
+    /// asking for the signature, so it must be the one users open.
+    pub siws_domain: String,
+    /// Marks the session cookie `Secure`. Turn off only for plain-HTTP local development.
+    pub secure_cookies: bool,
+    /// Session signing secret; an ephemeral one is generated when unset.
+    #[serde(serialize_with = "redact_secret")]
+    pub secret: Option<String>,
+    /// Enables the admin endpoints when set.
+    #[serde(serialize_with = "redact_secret")]
+    pub admin_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 3000,
+            log_format: LogFormat::Pretty,
+            log_filter: "info,sqlx=warn".to_string(),
+            metrics_addr: None,
+            database_url: "sqlite://arkheion.db?mode=rwc".to_string(),
+            db_max_connections: 5,
+            rpc_urls: vec!["https://api.mainnet-beta.solana.com".to_string()],
+            rpc_timeout_ms: 5_000,
+            poll_interval_ms: 2_000,
+            probe_interval_secs: 10,
+            degraded_latency_ms: 1_500,
+            degraded_slot_lag: rpc_pool::MAX_SLOT_LAG,
+            stall_after_secs: 20,
+            degraded_skip_rate: 0.25,
+            degraded_slot_time_ms: 800,
+            reconnecting_after_errors: 1,
+            down_after_errors: 10,
+            history_sample_secs: 2,
+            history_rollup_secs: 60,
+            raw_retention_hours: 72,
+            minute_retention_days: 7,
+            hour_retention_days: 90,
+            day_retention_days: 730,
+            reconcile_interval_secs: 15,
+            payment_expiry_minutes: 30,
+            treasury: None,
+            api_key_secret: None,
+            siws_domain: "localhost:3000".to_string(),
+            secure_cookies: true,
+            secret: None,
+            admin_token: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read { path: String, error: std::io::Error },
+    File { path: String, error: toml::de::Error },
+    DotEnv(dotenvy::Error),
+    /// An environment variable that is set but does not parse.
+    Env { var: String, message: String },
+    Invalid { key: &'static str, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, error } => write!(f, "cannot read {}: {}", path, error),
+            ConfigError::File { path, error } => write!(f, "invalid {}: {}", path, error),
+            ConfigError::DotEnv(e) => write!(f, "invalid .env: {}", e),
+            ConfigError::Env { var, message } => write!(f, "{}: {}", var, message),
+            ConfigError::Invalid { key, message } => {
+                write!(f, "{} ({}{}): {}", key, ENV_PREFIX, key.to_ascii_uppercase(), message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn invalid(key: &'static str, message: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid { key, message: message.into() }
+}
+
+impl Config {
+    /// Builds the layered config and validates it.
+    pub fn load() -> Result<Self, ConfigError> {
+        // dotenvy never overrides variables that are already set, which gives real env > .env.
+        // Loaded first so `.env` can also name the config file.
+        match dotenvy::dotenv() {
+            Ok(_) => {}
+            Err(e) if e.not_found() => {}
+            Err(e) => return Err(ConfigError::DotEnv(e)),
+        }
+
+        let path = std::env::var(format!("{}CONFIG", ENV_PREFIX)).unwrap_or_else(|_| DEFAULT_CONFIG_FILE.to_string());
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map_err(|error| ConfigError::File { path: path.clone(), error })?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+            Err(error) => return Err(ConfigError::Read { path, error }),
+        };
+
+        config.apply_env(|var| std::env::var(var).ok())?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Overrides every key whose `ARKHEION_*` variable `lookup` returns.
+    pub fn apply_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        let env = Env { lookup: &lookup };
+        env.parse("host", &mut self.host)?;
+        env.parse("port", &mut self.port)?;
+        env.parse("log_format", &mut self.log_format)?;
+        env.parse("log_filter", &mut self.log_filter)?;
+        env.parse_optional("metrics_addr", &mut self.metrics_addr)?;
+        env.parse("database_url", &mut self.database_url)?;
+        env.parse("db_max_connections", &mut self.db_max_connections)?;
+        if let Some(list) = env.get("rpc_urls") {
+            self.rpc_urls = list.split(',').map(|u| u.trim().to_string()).filter(|u| !u.is_empty()).collect();
+        }
+        env.parse("rpc_timeout_ms", &mut self.rpc_timeout_ms)?;
+        env.parse("poll_interval_ms", &mut self.poll_interval_ms)?;
+        env.parse("probe_interval_secs", &mut self.probe_interval_secs)?;
+        env.parse("degraded_latency_ms", &mut self.degraded_latency_ms)?;
+        env.parse("degraded_slot_lag", &mut self.degraded_slot_lag)?;
+        env.parse("stall_after_secs", &mut self.stall_after_secs)?;
+        env.parse("degraded_skip_rate", &mut self.degraded_skip_rate)?;
+        env.parse("degraded_slot_time_ms", &mut self.degraded_slot_time_ms)?;
+        env.parse("reconnecting_after_errors", &mut self.reconnecting_after_errors)?;
+        env.parse("down_after_errors", &mut self.down_after_errors)?;
+        env.parse("history_sample_secs", &mut self.history_sample_secs)?;
+        env.parse("history_rollup_secs", &mut self.history_rollup_secs)?;
+        env.parse("raw_retention_hours", &mut self.raw_retention_hours)?;
+        env.parse("minute_retention_days", &mut self.minute_retention_days)?;
+        env.parse("hour_retention_days", &mut self.hour_retention_days)?;
+        env.parse("day_retention_days", &mut self.day_retention_days)?;
+        env.parse("reconcile_interval_secs", &mut self.reconcile_interval_secs)?;
+        env.parse("payment_expiry_minutes", &mut self.payment_expiry_minutes)?;
+        env.optional("treasury", &mut self.treasury);
+        env.optional("api_key_secret", &mut self.api_key_secret);
+        env.parse("siws_domain", &mut self.siws_domain)?;
+        env.parse("secure_cookies", &mut self.secure_cookies)?;
+        env.optional("secret", &mut self.secret);
+        env.optional("admin_token", &mut self.admin_token);
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.port == 0 {
+            return Err(invalid("port", "must be between 1 and 65535"));
+        }
+        if let Err(e) = telemetry::parse_filter(&self.log_filter) {
+            return Err(invalid("log_filter", e));
+        }
+        if self.db_max_connections == 0 {
+            return Err(invalid("db_max_connections", "must be at least 1"));
+        }
+        if self.rpc_urls.is_empty() {
+            return Err(invalid("rpc_urls", "at least one RPC endpoint is required"));
+        }
+        if let Some(url) = self.rpc_urls.iter().find(|u| !u.starts_with("http://") && !u.starts_with("https://")) {
+            return Err(invalid("rpc_urls", format!("'{}' is not an http(s) URL", rpc_pool::redact(url))));
+        }
+        if self.rpc_timeout_ms < 100 {
+            return Err(invalid("rpc_timeout_ms", "must be at least 100"));
+        }
+        if self.poll_interval_ms < 100 {
+            return Err(invalid("poll_interval_ms", "must be at least 100"));
+        }
+        if self.probe_interval_secs == 0 {
+            return Err(invalid("probe_interval_secs", "must be greater than zero"));
+        }
+        if self.stall_after_secs == 0 {
+            return Err(invalid("stall_after_secs", "must be greater than zero"));
+        }
+        if !(0.0..=1.0).contains(&self.degraded_skip_rate) {
+            return Err(invalid("degraded_skip_rate", "must be between 0 and 1"));
+        }
+        if self.reconnecting_after_errors == 0 {
+            return Err(invalid("reconnecting_after_errors", "must be at least 1"));
+        }
+        if self.down_after_errors < self.reconnecting_after_errors {
+            return Err(invalid("down_after_errors", "must be at least reconnecting_after_errors"));
+        }
+        if self.reconcile_interval_secs == 0 {
+            return Err(invalid("reconcile_interval_secs", "must be greater than zero"));
+        }
+        if self.payment_expiry_minutes <= 0 {
+            return Err(invalid("payment_expiry_minutes", "must be greater than zero"));
+        }
+        if let Some(treasury) = &self.treasury {
+            if Pubkey::from_str(treasury).is_err() {
+                return Err(invalid("treasury", "not a valid Solana address"));
+            }
+        }
+        if self.api_key_secret.as_ref().is_some_and(|s| s.len() < 32) {
+            return Err(invalid("api_key_secret", "must be at least 32 characters"));
+        }
+        if self.siws_domain.is_empty() || self.siws_domain.contains(['/', ' ']) {
+            return Err(invalid("siws_domain", "must be a bare host[:port], e.g. console.example.com"));
+        }
+        if self.history_sample_secs == 0 {
+            return Err(invalid("history_sample_secs", "must be greater than zero"));
+        }
+        if self.history_rollup_secs == 0 {
+            return Err(invalid("history_rollup_secs", "must be greater than zero"));
+        }
+        // Every rollup is computed from raw samples, so they must outlive the largest bucket.
+        if self.raw_retention_hours.saturating_mul(3_600) <= Resolution::Day.bucket_secs() {
+            return Err(invalid("raw_retention_hours", "must be longer than one day"));
+        }
+        for (key, days) in [
+            ("minute_retention_days", self.minute_retention_days),
+            ("hour_retention_days", self.hour_retention_days),
+            ("day_retention_days", self.day_retention_days),
+        ] {
+            if days <= 0 {
+                return Err(invalid(key, "must be greater than zero"));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn bind_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.port)
+    }
+
+    pub fn rpc_timeout(&self) -> Duration {
+        Duration::from_millis(self.rpc_timeout_ms)
+    }
+
+    pub fn engine(&self) -> EngineConfig {
+        EngineConfig {
+            poll_interval: Duration::from_millis(self.poll_interval_ms),
+            probe_interval: Duration::from_secs(self.probe_interval_secs),
+            thresholds: StatusThresholds {
+                degraded_latency: Duration::from_millis(self.degraded_latency_ms),
+                degraded_slot_lag: self.degraded_slot_lag,
+                stall_after: Duration::from_secs(self.stall_after_secs),
+                degraded_skip_rate: self.degraded_skip_rate,
+                degraded_slot_time: Duration::from_millis(self.degraded_slot_time_ms),
+                reconnecting_after_errors: self.reconnecting_after_errors,
+                down_after_errors: self.down_after_errors,
+            },
+        }
+    }
+
+    pub fn history(&self) -> HistoryConfig {
+        HistoryConfig {
+            sample_interval: Duration::from_secs(self.history_sample_secs),
+            rollup_interval: Duration::from_secs(self.history_rollup_secs),
+            raw_retention: ChronoDuration::hours(self.raw_retention_hours),
+            minute_retention: ChronoDuration::days(self.minute_retention_days),
+            hour_retention: ChronoDuration::days(self.hour_retention_days),
+            day_retention: ChronoDuration::days(self.day_retention_days),
+        }
+    }
+
+    pub fn reconciler(&self) -> ReconcilerConfig {
+        ReconcilerConfig {
+            interval: Duration::from_secs(self.reconcile_interval_secs),
+            expiry: ChronoDuration::minutes(self.payment_expiry_minutes),
+            ..ReconcilerConfig::default()
+        }
+    }
+}
+
+struct Env<'a> {
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl Env<'_> {
+    fn var(key: &str) -> String {
+        format!("{}{}", ENV_PREFIX, key.to_ascii_uppercase())
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        (self.lookup)(&Self::var(key)).filter(|v| !v.is_empty())
+    }
+
+    fn parse<T: FromStr>(&self, key: &str, slot: &mut T) -> Result<(), ConfigError>
+    where
+        T::Err: fmt::Display,
+    {
+        let mut value = None;
+        self.parse_optional(key, &mut value)?;
+        if let Some(value) = value {
+            *slot = value;
+        }
+        Ok(())
+    }
+
+    fn parse_optional<T: FromStr>(&self, key: &str, slot: &mut Option<T>) -> Result<(), ConfigError>
+    where
+        T::Err: fmt::Display,
+    {
+        if let Some(raw) = self.get(key) {
+            let value = raw.trim().parse().map_err(|e: T::Err| ConfigError::Env {
+                var: Self::var(key),
+                message: e.to_string(),
+            })?;
+            *slot = Some(value);
+        }
+        Ok(())
+    }
+
+    fn optional(&self, key: &str, slot: &mut Option<String>) {
+        if let Some(value) = self.get(key) {
+            *slot = Some(value);
+        }
+    }
+}
+
+fn redact_secret<S: Serializer>(value: &Option<String>, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(if value.is_some() { "<redacted>" } else { "<unset>" })
+}
+
+fn redact_urls<S: Serializer>(urls: &[String], s: S) -> Result<S::Ok, S::Error> {
+    s.collect_seq(urls.iter().map(|u| rpc_pool::redact(u)))
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Installs the process-wide config. Called once from `main` before anything reads it.
+pub fn init(config: Config) {
+    CONFIG.set(config).expect("config::init called twice");
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("config::init must run before the config is read")
+}
+
+/// Installs the defaults plus a fixed API key secret and treasury, unless a test already did.
+#[cfg(test)]
+pub fn init_for_tests() -> &'static Config {
+    CONFIG.get_or_init(|| Config {
+        api_key_secret: Some("unit-test-secret-unit-test-secret-0".to_string()),
+        treasury: Some("Vote111111111111111111111111111111111111111".to_string()),
+        ..Config::default()
+    })
+}
+
+/// The running configuration with secrets and provider URLs redacted.
+/// Requires `X-Admin-Token`; answers 404 when no admin token is configured.
+pub async fn admin_config(headers: HeaderMap) -> Response {
+    let config = get();
+    let Some(expected) = config.admin_token.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let presented = headers.get("x-admin-token").and_then(|v| v.to_str().ok()).unwrap_or("");
+    // Compare digests so the check does not leak the token length or prefix through timing.
+    let (a, b) = (Sha256::digest(presented.as_bytes()), Sha256::digest(expected.as_bytes()));
+    if a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) != 0 {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "Invalid admin token" }))).into_response();
+    }
+
+    Json(json!({ "config": config })).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |var| vars.get(var).cloned()
+    }
+
+    /// The key `validate` rejects after `change` is applied to the defaults.
+    fn rejected_key(change: impl FnOnce(&mut Config)) -> &'static str {
+        let mut config = Config::default();
+        change(&mut config);
+        match config.validate() {
+            Err(ConfigError::Invalid { key, .. }) => key,
+            other => panic!("expected an invalid key, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        Config::default().validate().unwrap();
+    }
+
+    #[test]
+    fn env_overrides_file_overrides_defaults() {
+        let mut config: Config = toml::from_str(
+            "port = 4000\nrpc_urls = [\"http://file\"]\ndegraded_skip_rate = 0.5\n",
+        )
+        .unwrap();
+        config.apply_env(env(&[
+            ("ARKHEION_PORT", "5000"),
+            ("ARKHEION_RPC_URLS", "http://a, ,http://b"),
+            ("ARKHEION_TREASURY", ""),
+            ("PORT", "6000"),
+        ]))
+        .unwrap();
+
+        assert_eq!(config.port, 5000);
+        assert_eq!(config.rpc_urls, vec!["http://a", "http://b"]);
+        assert_eq!(config.degraded_skip_rate, 0.5);
+        assert_eq!(config.poll_interval_ms, Config::default().poll_interval_ms);
+        // Empty variables count as unset.
+        assert_eq!(config.treasury, None);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn unknown_file_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("prot = 4000\n").is_err());
+    }
+
+    #[test]
+    fn unparsable_env_names_the_variable() {
+        let mut config = Config::default();
+        match config.apply_env(env(&[("ARKHEION_PORT", "eighty")])) {
+            Err(ConfigError::Env { var, .. }) => assert_eq!(var, "ARKHEION_PORT"),
+            other => panic!("expected an env error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn validate_names_the_offending_key() {
+        assert_eq!(rejected_key(|c| c.port = 0), "port");
+        assert_eq!(rejected_key(|c| c.rpc_urls = vec!["ws://node".into()]), "rpc_urls");
+        assert_eq!(rejected_key(|c| c.rpc_urls.clear()), "rpc_urls");
+        assert_eq!(rejected_key(|c| c.degraded_skip_rate = 1.5), "degraded_skip_rate");
+        assert_eq!(rejected_key(|c| c.down_after_errors = 0), "down_after_errors");
+        assert_eq!(rejected_key(|c| c.treasury = Some("not-an-address".into())), "treasury");
+        assert_eq!(rejected_key(|c| c.api_key_secret = Some("short".into())), "api_key_secret");
+        assert_eq!(rejected_key(|c| c.siws_domain = "https://example.com".into()), "siws_domain");
+        assert_eq!(rejected_key(|c| c.history_sample_secs = 0), "history_sample_secs");
+        assert_eq!(rejected_key(|c| c.history_rollup_secs = 0), "history_rollup_secs");
+        assert_eq!(rejected_key(|c| c.raw_retention_hours = 24), "raw_retention_hours");
+        assert_eq!(rejected_key(|c| c.minute_retention_days = 0), "minute_retention_days");
+        assert_eq!(rejected_key(|c| c.hour_retention_days = -1), "hour_retention_days");
+        assert_eq!(rejected_key(|c| c.day_retention_days = -30), "day_retention_days");
+    }
+}
@@ -1,46 +1,254 @@
-use sqlx::{sqlite::SqlitePoolOptions, Pool, Sqlite, Error};
-use std::fs::File;
-use std::path::Path;
-
-pub async fn init_db() -> Result<Pool<Sqlite>, Error> {
-    if !Path::new("arkheion.db").exists() {
-        File::create("arkheion.db").expect("Gagal membuat file database");
-        println!(">>> Database file created: arkheion.db");
+use sqlx::{sqlite::SqlitePoolOptions, Connection, Pool, Sqlite, Error};
+use crate::config::Config;
+use crate::migrations::{self, MigrateError};
+
+// Frozen shapes from migration 0001, used only to upgrade databases that predate
+// versioned migrations. New columns go in a new migration, not here.
+const USERS_COLUMNS: &str = "(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    public_key TEXT UNIQUE,
+    email TEXT UNIQUE,
+    api_key TEXT UNIQUE NOT NULL,
+    tier TEXT DEFAULT 'free',
+    credits INTEGER DEFAULT 1000,
+    requests INTEGER DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    last_active DATETIME DEFAULT CURRENT_TIMESTAMP,
+    CHECK (public_key IS NOT NULL OR email IS NOT NULL)
+)";
+
+const PAYMENT_TX_COLUMNS: &str = "(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    signature TEXT UNIQUE NOT NULL,
+    amount_sol REAL NOT NULL,
+    plan TEXT NOT NULL,
+    status TEXT DEFAULT 'pending',
+    failure_reason TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_check_at DATETIME,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY(user_id) REFERENCES users(id)
+)";
+
+/// `mode=rwc` in the default URL creates the database file on first start.
+pub async fn connect(config: &Config) -> Result<Pool<Sqlite>, Error> {
+    SqlitePoolOptions::new()
+        .max_connections(config.db_max_connections)
+        .connect(&config.database_url)
+        .await
+}
+
+pub async fn init_db(config: &Config) -> Result<Pool<Sqlite>, MigrateError> {
+    let pool = connect(config).await?;
+    upgrade(&pool).await?;
+    tracing::info!(schema_version = migrations::latest_version(), "database initialized");
+    Ok(pool)
+}
+
+/// Brings any database this server has ever created up to the current schema.
+pub async fn upgrade(pool: &Pool<Sqlite>) -> Result<(), MigrateError> {
+    upgrade_unversioned(pool).await?;
+    migrations::run(pool).await
+}
+
+async fn columns(pool: &Pool<Sqlite>, table: &str) -> Result<Vec<String>, Error> {
+    sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(pool)
+        .await
+}
+
+/// Databases from before `schema_migrations` existed were built with CREATE TABLE IF NOT
+/// EXISTS, so their tables may be older shapes that migration 0001 would silently keep:
+/// the email-only `users` of the v1 server, the wallet-only one (`public_key NOT NULL`),
+/// and a `payment_tx` without plan/retry columns. Rebuild those, keeping every row.
+async fn upgrade_unversioned(pool: &Pool<Sqlite>) -> Result<(), Error> {
+    if !columns(pool, "schema_migrations").await?.is_empty() {
+        return Ok(());
     }
 
-    let pool = SqlitePoolOptions::new()
-        .max_connections(5) 
-        .connect("sqlite://arkheion.db?mode=rwc")
-        .await?;
-
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS users (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            public_key TEXT UNIQUE NOT NULL,
-            api_key TEXT UNIQUE NOT NULL,
-            tier TEXT DEFAULT 'free',
-            credits INTEGER DEFAULT 1000,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            last_active DATETIME DEFAULT CURRENT_TIMESTAMP
-        )",
-    )
-    .execute(&pool)
+    // v1 tiers were capitalised ('Free'), and v1 stored guessable plaintext keys
+    // (not unique either): those are invalidated.
+    rebuild_table(pool, "users", USERS_COLUMNS, &["email", "public_key", "requests"], &[
+        ("id", "id", None),
+        ("public_key", "public_key", None),
+        ("email", "email", None),
+        ("api_key", "CASE WHEN api_key LIKE '%:%' THEN api_key ELSE 'unhashed:' || id END", None),
+        ("tier", "lower(tier)", None),
+        ("credits", "credits", None),
+        ("requests", "requests", None),
+        ("created_at", "created_at", None),
+        ("last_active", "last_active", Some("created_at")),
+    ])
     .await?;
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS payment_tx (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            user_id INTEGER NOT NULL,
-            signature TEXT UNIQUE NOT NULL,
-            amount_sol REAL NOT NULL,
-            status TEXT DEFAULT 'pending',
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY(user_id) REFERENCES users(id)
-        )",
-    )
-    .execute(&pool)
-    .await?;
+    rebuild_table(pool, "payment_tx", PAYMENT_TX_COLUMNS, &["plan", "attempts", "next_check_at", "updated_at"], &[
+        ("id", "id", None),
+        ("user_id", "user_id", None),
+        ("signature", "signature", None),
+        ("amount_sol", "amount_sol", None),
+        ("plan", "plan", Some("'legacy'")),
+        ("status", "status", None),
+        ("failure_reason", "failure_reason", None),
+        ("attempts", "attempts", None),
+        ("next_check_at", "next_check_at", None),
+        ("created_at", "created_at", None),
+        ("updated_at", "updated_at", Some("created_at")),
+    ])
+    .await
+}
 
-    println!(">>> Database initialized successfully.");
-    Ok(pool)
+/// Rebuilds `table` into `shape` unless it is missing or already has all of `required`.
+/// `carried` is `(column, expr when the old table has it, expr when it doesn't)`.
+async fn rebuild_table(
+    pool: &Pool<Sqlite>,
+    table: &str,
+    shape: &str,
+    required: &[&str],
+    carried: &[(&str, &str, Option<&str>)],
+) -> Result<(), Error> {
+    let existing = columns(pool, table).await?;
+    let has = |c: &str| existing.iter().any(|x| x == c);
+    if existing.is_empty() || required.iter().all(|c| has(c)) {
+        return Ok(());
+    }
+
+    let carried: Vec<(&str, &str)> = carried.iter()
+        .filter_map(|(col, expr, fallback)| if has(col) { Some((*col, *expr)) } else { fallback.map(|f| (*col, f)) })
+        .collect();
+    let cols = carried.iter().map(|(c, _)| *c).collect::<Vec<_>>().join(", ");
+    let exprs = carried.iter().map(|(_, e)| *e).collect::<Vec<_>>().join(", ");
+
+    // Dropping a parent table trips foreign key checks, and the pragma can't change
+    // inside a transaction, so do the rebuild on one connection with checks off.
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut *conn).await?;
+
+    let copied = async {
+        let mut tx = conn.begin().await?;
+        sqlx::query(&format!("CREATE TABLE {}_new {}", table, shape)).execute(&mut *tx).await?;
+        sqlx::query(&format!("INSERT INTO {}_new ({}) SELECT {} FROM {}", table, cols, exprs, table)).execute(&mut *tx).await?;
+        sqlx::query(&format!("DROP TABLE {}", table)).execute(&mut *tx).await?;
+        sqlx::query(&format!("ALTER TABLE {}_new RENAME TO {}", table, table)).execute(&mut *tx).await?;
+        tx.commit().await
+    }
+    .await;
+
+    // The connection goes back to the pool whatever happened, so checks must come back on
+    // even after a failed copy; one that can't be restored is closed instead.
+    if let Err(e) = sqlx::query("PRAGMA foreign_keys = ON").execute(&mut *conn).await {
+        drop(conn.detach());
+        return Err(copied.err().unwrap_or(e));
+    }
+    copied?;
+    tracing::info!(table, columns = carried.len(), "upgraded legacy table");
+    Ok(())
+}
+
+/// A migrated in-memory database for unit tests. One connection: every connection to
+/// `sqlite::memory:` is its own database.
+#[cfg(test)]
+pub async fn memory_pool() -> Pool<Sqlite> {
+    let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    migrations::run(&pool).await.unwrap();
+    pool
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One connection: every connection to `sqlite::memory:` is its own database.
+    async fn memory_db(schema: &str) -> Pool<Sqlite> {
+        let db = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        sqlx::raw_sql(schema).execute(&db).await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn email_schema_upgrades_without_losing_rows() {
+        // The v1 server's table: email-only, plaintext keys, capitalised tiers.
+        let db = memory_db(
+            "CREATE TABLE users (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                email TEXT UNIQUE NOT NULL,
+                api_key TEXT NOT NULL,
+                tier TEXT DEFAULT 'Free',
+                requests INTEGER DEFAULT 0,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+            INSERT INTO users (email, api_key, tier, requests, created_at) VALUES
+                ('a@example.com', 'ARK-guessable', 'Free', 12, '2024-01-02 03:04:05'),
+                ('b@example.com', 'k3y1d:00ff', 'Pro', 0, '2024-02-03 04:05:06');",
+        )
+        .await;
+
+        upgrade(&db).await.unwrap();
+
+        let users: Vec<(i64, String, String, String, i64, i64, String)> = sqlx::query_as(
+            "SELECT id, email, api_key, tier, credits, requests, last_active FROM users ORDER BY id",
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        assert_eq!(users, vec![
+            (1, "a@example.com".into(), "unhashed:1".into(), "free".into(), 1000, 12, "2024-01-02 03:04:05".into()),
+            (2, "b@example.com".into(), "k3y1d:00ff".into(), "pro".into(), 1000, 0, "2024-02-03 04:05:06".into()),
+        ]);
+        assert_eq!(columns(&db, "users").await.unwrap().len(), 9);
+    }
+
+    #[tokio::test]
+    async fn wallet_schema_keeps_credits_and_payments() {
+        // The pre-migration init_db: wallet-only users, payments without plan or retries.
+        let db = memory_db(
+            "CREATE TABLE users (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                public_key TEXT UNIQUE NOT NULL,
+                api_key TEXT UNIQUE NOT NULL,
+                tier TEXT DEFAULT 'free',
+                credits INTEGER DEFAULT 1000,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_active DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE TABLE payment_tx (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                signature TEXT UNIQUE NOT NULL,
+                amount_sol REAL NOT NULL,
+                status TEXT DEFAULT 'pending',
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(user_id) REFERENCES users(id)
+            );
+            INSERT INTO users (public_key, api_key, credits) VALUES ('WalletA', 'a:00', 42), ('WalletB', 'b:00', 0);
+            INSERT INTO payment_tx (user_id, signature, amount_sol, status, created_at) VALUES
+                (1, 'sig1', 0.05, 'confirmed', '2024-03-04 05:06:07'),
+                (2, 'sig2', 0.5, 'pending', '2024-03-05 05:06:07');",
+        )
+        .await;
+
+        upgrade(&db).await.unwrap();
+        // A second start finds everything versioned and changes nothing.
+        upgrade(&db).await.unwrap();
+
+        let credits: Vec<(String, i64, i64)> =
+            sqlx::query_as("SELECT public_key, credits, requests FROM users ORDER BY id").fetch_all(&db).await.unwrap();
+        assert_eq!(credits, vec![("WalletA".into(), 42, 0), ("WalletB".into(), 0, 0)]);
+
+        let payments: Vec<(i64, String, String, String, i64, String)> = sqlx::query_as(
+            "SELECT user_id, signature, plan, status, attempts, updated_at FROM payment_tx ORDER BY id",
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        assert_eq!(payments, vec![
+            (1, "sig1".into(), "legacy".into(), "confirmed".into(), 0, "2024-03-04 05:06:07".into()),
+            (2, "sig2".into(), "legacy".into(), "pending".into(), 0, "2024-03-05 05:06:07".into()),
+        ]);
+
+        let fk_on: i64 = sqlx::query_scalar("PRAGMA foreign_keys").fetch_one(&db).await.unwrap();
+        assert_eq!(fk_on, 1);
+    }
 }
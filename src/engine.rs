@@ -1,55 +1,1074 @@
-use solana_client::rpc_client::RpcClient;
-use solana_sdk::commitment_config::CommitmentConfig;
-use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_response::RpcPerfSample;
+use solana_sdk::epoch_info::EpochInfo;
+use std::collections::VecDeque;
+use std::sync::Arc;
 use tokio::time::{sleep, Duration, Instant};
+use tracing::Instrument;
 use serde::Serialize;
+use crate::exporter;
+use crate::rpc_pool::{redact, ProbeReport, RpcPool, MAX_SLOT_LAG};
+use crate::stream::MetricsHub;
 
+// getRecentPerformanceSamples returns one sample per ~60s, so 5 samples ≈ the last 5 minutes.
+const PERF_SAMPLE_LIMIT: usize = 5;
+const PERF_SAMPLE_REFRESH: Duration = Duration::from_secs(30);
+// Slots land every ~400ms; this much silence means the subscription is dead even if the socket is not.
+const SLOT_NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+const PUBSUB_RETRY_MIN: Duration = Duration::from_secs(1);
+const PUBSUB_RETRY_MAX: Duration = Duration::from_secs(30);
+/// How often the slot subscription checks whether the pool has failed over.
+const FAILOVER_CHECK: Duration = Duration::from_secs(1);
+/// Slot advances remembered for the average slot time.
+const SLOT_RATE_WINDOW: Duration = Duration::from_secs(60);
+/// No average slot time until the window covers at least this much.
+const SLOT_RATE_MIN_SPAN: Duration = Duration::from_secs(5);
+// Skip rate over the last ~2 minutes of slots, refreshed twice a minute.
+const SKIP_RATE_SLOTS: u64 = 300;
+const SKIP_RATE_REFRESH: Duration = Duration::from_secs(30);
+/// Without a root from the slot subscription, blocks are checked this far below the
+/// slot so the most recent ones have had time to confirm.
+const SKIP_RATE_DEPTH: u64 = 32;
+
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Delay between `getEpochInfo` polls.
+    pub poll_interval: Duration,
+    /// How often every pool endpoint is probed for lag and forks.
+    pub probe_interval: Duration,
+    pub thresholds: StatusThresholds,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(2),
+            probe_interval: Duration::from_secs(10),
+            thresholds: StatusThresholds::default(),
+        }
+    }
+}
+
+/// When the engine moves between [`EngineStatus`]es.
+#[derive(Debug, Clone)]
+pub struct StatusThresholds {
+    /// `getEpochInfo` slower than this is `Degraded`.
+    pub degraded_latency: Duration,
+    /// The active endpoint trailing the cluster tip by more slots than this is `Degraded`.
+    pub degraded_slot_lag: u64,
+    /// A slot that has not advanced for this long is `Degraded`.
+    pub stall_after: Duration,
+    /// A share of skipped slots (0 to 1) above this is `Degraded`.
+    pub degraded_skip_rate: f64,
+    /// An average slot time above this is `Degraded`.
+    pub degraded_slot_time: Duration,
+    /// Failed updates in a row before leaving `Operational` / `Degraded` for `Reconnecting`.
+    pub reconnecting_after_errors: u32,
+    /// Failed updates in a row before `Down`.
+    pub down_after_errors: u32,
+}
+
+impl Default for StatusThresholds {
+    fn default() -> Self {
+        Self {
+            degraded_latency: Duration::from_millis(1_500),
+            degraded_slot_lag: MAX_SLOT_LAG,
+            stall_after: Duration::from_secs(20),
+            degraded_skip_rate: 0.25,
+            degraded_slot_time: Duration::from_millis(800),
+            reconnecting_after_errors: 1,
+            down_after_errors: 10,
+        }
+    }
+}
+
+impl StatusThresholds {
+    /// Every degraded condition that holds after a successful update, most severe first:
+    /// stall, lag, skip rate, slot time, latency. The first one is the status reason.
+    pub fn conditions(&self, m: &EngineMetrics, stalled_for: Duration) -> Vec<(StatusReason, String)> {
+        let mut found = Vec::new();
+        if stalled_for >= self.stall_after {
+            let detail = format!("slot {} has not advanced for {}s", m.slot, stalled_for.as_secs());
+            found.push((StatusReason::SlotStall, detail));
+        }
+        if m.slot_lag > self.degraded_slot_lag {
+            let detail = format!("active endpoint is {} slots behind the cluster tip", m.slot_lag);
+            found.push((StatusReason::NodeLagging, detail));
+        }
+        if let Some(rate) = m.skip_rate.filter(|r| *r > self.degraded_skip_rate) {
+            let detail = format!("{:.1}% of the last {} slots were skipped", rate * 100.0, SKIP_RATE_SLOTS);
+            found.push((StatusReason::HighSkipRate, detail));
+        }
+        if let Some(ms) = m.slot_time_ms.filter(|ms| *ms as u128 > self.degraded_slot_time.as_millis()) {
+            found.push((StatusReason::SlowSlots, format!("average slot time is {}ms", ms)));
+        }
+        if m.latency > self.degraded_latency.as_millis() {
+            found.push((StatusReason::HighLatency, format!("getEpochInfo took {}ms", m.latency)));
+        }
+        found
+    }
+}
+
+/// Slot progress over a sliding window, for stall detection and the average slot time,
+/// plus the skip rate from the last `getBlocks` check.
+#[derive(Default)]
+pub struct SlotTracker {
+    /// (slot, when it was first seen), one entry per advance, oldest first.
+    advances: VecDeque<(u64, Instant)>,
+    last_skip_check: Option<Instant>,
+    skip_rate: Option<f64>,
+}
+
+impl SlotTracker {
+    pub fn observe(&mut self, slot: u64, now: Instant) {
+        if self.advances.back().map_or(true, |(last, _)| slot > *last) {
+            self.advances.push_back((slot, now));
+        }
+        // The newest entry stays however old it is: it marks where a stall began.
+        while self.advances.len() > 1 && now.duration_since(self.advances[0].1) > SLOT_RATE_WINDOW {
+            self.advances.pop_front();
+        }
+    }
+
+    /// How long the highest slot seen has gone without advancing.
+    pub fn stalled_for(&self, now: Instant) -> Duration {
+        self.advances.back().map_or(Duration::ZERO, |(_, at)| now.duration_since(*at))
+    }
+
+    /// Time per slot across the window; `None` until it spans `SLOT_RATE_MIN_SPAN`.
+    pub fn avg_slot_time(&self) -> Option<Duration> {
+        let (first, last) = (self.advances.front()?, self.advances.back()?);
+        let span = last.1.duration_since(first.1);
+        if span < SLOT_RATE_MIN_SPAN {
+            return None;
+        }
+        Some(Duration::from_secs_f64(span.as_secs_f64() / (last.0 - first.0) as f64))
+    }
+
+    /// The slots to check for skips, when a check is due: the `SKIP_RATE_SLOTS` up to
+    /// and including `end`.
+    pub fn skip_range(&mut self, end: u64, now: Instant) -> Option<(u64, u64)> {
+        if end < SKIP_RATE_SLOTS {
+            return None;
+        }
+        if self.last_skip_check.is_some_and(|t| now.duration_since(t) < SKIP_RATE_REFRESH) {
+            return None;
+        }
+        self.last_skip_check = Some(now);
+        Some((end + 1 - SKIP_RATE_SLOTS, end))
+    }
+
+    /// Stores the `getBlocks` answer for `range`; `None` when the call failed.
+    pub fn record_blocks(&mut self, (start, end): (u64, u64), blocks: Option<&[u64]>) {
+        self.skip_rate = blocks.map(|blocks| {
+            let produced = blocks.iter().filter(|slot| (start..=end).contains(*slot)).count();
+            1.0 - produced as f64 / (end - start + 1) as f64
+        });
+    }
+
+    pub fn skip_rate(&self) -> Option<f64> {
+        self.skip_rate
+    }
+}
+
+/// Where `EngineMetrics::slot` currently comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SlotSource {
+    /// `slotSubscribe` notifications, updated on every slot.
+    Websocket,
+    /// `getEpochInfo` on the engine tick; used while the subscription is down.
+    Polling,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TpsMethod {
+    PerformanceSamples,
+    TransactionCountDelta,
+    Unavailable,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Throughput {
+    pub tps: Option<u64>,
+    pub tps_non_vote: Option<u64>,
+    pub tps_vote: Option<u64>,
+    pub window_secs: u64,
+    pub method: TpsMethod,
+}
+
+impl Default for Throughput {
+    fn default() -> Self {
+        Self {
+            tps: None, tps_non_vote: None, tps_vote: None, window_secs: 0,
+            method: TpsMethod::Unavailable,
+        }
+    }
+}
+
+impl Throughput {
+    /// Averages the samples over their combined period. Vote/non-vote split is only
+    /// reported when every sample carries `num_non_vote_transactions`.
+    pub fn from_perf_samples(samples: &[RpcPerfSample]) -> Option<Self> {
+        let window_secs: u64 = samples.iter().map(|s| s.sample_period_secs as u64).sum();
+        if window_secs == 0 {
+            return None;
+        }
+
+        let total: u64 = samples.iter().map(|s| s.num_transactions).sum();
+        let non_vote: Option<u64> = samples.iter().map(|s| s.num_non_vote_transactions).sum();
+
+        Some(Self {
+            tps: Some(total / window_secs),
+            tps_non_vote: non_vote.map(|n| n / window_secs),
+            tps_vote: non_vote.map(|n| total.saturating_sub(n) / window_secs),
+            window_secs,
+            method: TpsMethod::PerformanceSamples,
+        })
+    }
+
+    /// Fallback when the node does not serve performance samples: the change in
+    /// `transaction_count` between two `getEpochInfo` calls. Only a total is available.
+    pub fn from_tx_count_delta(prev: (u64, Instant), now: (u64, Instant)) -> Option<Self> {
+        let elapsed = now.1.duration_since(prev.1).as_secs_f64();
+        if elapsed < 1.0 || now.0 < prev.0 {
+            return None;
+        }
+
+        Some(Self {
+            tps: Some(((now.0 - prev.0) as f64 / elapsed) as u64),
+            window_secs: elapsed.round() as u64,
+            method: TpsMethod::TransactionCountDelta,
+            ..Self::default()
+        })
+    }
+}
+
+/// Keeps the state needed to derive TPS between engine ticks.
+#[derive(Default)]
+pub struct ThroughputTracker {
+    last_sample_fetch: Option<Instant>,
+    last_samples: Option<Throughput>,
+    last_tx_count: Option<(u64, Instant)>,
+}
+
+impl ThroughputTracker {
+    /// Whether this tick should fetch performance samples; the node only adds one a minute.
+    pub fn wants_samples(&mut self) -> bool {
+        let now = Instant::now();
+        let due = self.last_sample_fetch.map_or(true, |t| now.duration_since(t) >= PERF_SAMPLE_REFRESH);
+        if due {
+            self.last_sample_fetch = Some(now);
+        }
+        due
+    }
+
+    /// Stores the outcome of a sample fetch; `None` when it failed.
+    pub fn record_samples(&mut self, samples: Option<&[RpcPerfSample]>) {
+        self.last_samples = samples.and_then(Throughput::from_perf_samples);
+    }
+
+    pub fn update(&mut self, info: &EpochInfo) -> Throughput {
+        let now = Instant::now();
+
+        let delta = match (self.last_tx_count, info.transaction_count) {
+            (Some(prev), Some(count)) => Throughput::from_tx_count_delta(prev, (count, now)),
+            _ => None,
+        };
+        if let Some(count) = info.transaction_count {
+            // Only move the baseline once the window is long enough to be meaningful.
+            if delta.is_some() || self.last_tx_count.is_none() {
+                self.last_tx_count = Some((count, now));
+            }
+        }
+
+        self.last_samples.clone().or(delta).unwrap_or_default()
+    }
+}
+
+/// Serialized upper-case, as the status has always been on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EngineStatus {
+    /// No successful update yet.
+    Booting,
+    Operational,
+    /// Answering, but stalled, behind the cluster or slow; see the reason.
+    Degraded,
+    /// Updates are failing; the pool keeps failing over.
+    Reconnecting,
+    /// Updates have failed `down_after_errors` times in a row.
+    Down,
+}
+
+impl EngineStatus {
+    pub const ALL: [EngineStatus; 5] = [
+        EngineStatus::Booting,
+        EngineStatus::Operational,
+        EngineStatus::Degraded,
+        EngineStatus::Reconnecting,
+        EngineStatus::Down,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngineStatus::Booting => "BOOTING",
+            EngineStatus::Operational => "OPERATIONAL",
+            EngineStatus::Degraded => "DEGRADED",
+            EngineStatus::Reconnecting => "RECONNECTING",
+            EngineStatus::Down => "DOWN",
+        }
+    }
+}
+
+/// What put the engine in its current status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusReason {
+    Startup,
+    Healthy,
+    HighLatency,
+    SlotStall,
+    NodeLagging,
+    HighSkipRate,
+    SlowSlots,
+    RpcErrors,
+}
+
+impl StatusReason {
+    /// For slot conditions: the alert raised when it starts, the one raised when it
+    /// clears, and the detail of the latter.
+    fn alert_kinds(&self) -> Option<(AlertKind, AlertKind, &'static str)> {
+        match self {
+            StatusReason::SlotStall => Some((AlertKind::SlotsStalled, AlertKind::SlotsResumed, "slot is advancing again")),
+            StatusReason::HighSkipRate => Some((AlertKind::HighSkipRate, AlertKind::SkipRateNormal, "skip rate back under threshold")),
+            StatusReason::SlowSlots => Some((AlertKind::SlowSlots, AlertKind::SlotTimeNormal, "average slot time back under threshold")),
+            _ => None,
+        }
+    }
+}
+
+/// One entry of the transition log kept by `MetricsHub`.
 #[derive(Debug, Clone, Serialize)]
+pub struct StatusTransition {
+    pub from: EngineStatus,
+    pub to: EngineStatus,
+    pub reason: StatusReason,
+    pub detail: String,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct EngineMetrics {
     pub slot: u64,
-    pub tps: u64,
+    pub slot_source: SlotSource,
+    /// Latest rooted slot; only known while the slot subscription is up.
+    pub root: Option<u64>,
+    /// Highest slot seen across all configured endpoints, and how far the active one trails it.
+    pub cluster_slot: Option<u64>,
+    pub slot_lag: u64,
+    pub lagging_nodes: Vec<String>,
+    pub forked_nodes: Vec<String>,
+    pub tps: Option<u64>,
+    pub tps_non_vote: Option<u64>,
+    pub tps_vote: Option<u64>,
+    pub tps_window_secs: u64,
+    pub tps_method: TpsMethod,
+    /// Average over the last minute of slot advances.
+    pub slot_time_ms: Option<u64>,
+    /// Share of recent slots (0 to 1) that produced no block.
+    pub skip_rate: Option<f64>,
     pub epoch: u64,
     pub latency: u128,
-    pub status: String,
+    pub status: EngineStatus,
+    pub status_reason: StatusReason,
+    /// Human-readable specifics of `status_reason`, e.g. the latency that was measured.
+    pub status_detail: String,
+    /// When `status` or `status_reason` last changed.
+    pub status_since: DateTime<Utc>,
+    /// Engine updates failed in a row; reset by the next success.
+    pub consecutive_errors: u32,
+    pub last_success: Option<DateTime<Utc>>,
 }
 
 impl Default for EngineMetrics {
     fn default() -> Self {
         Self {
-            slot: 0, tps: 0, epoch: 0, latency: 0,
-            status: "BOOTING".to_string(),
+            slot: 0, slot_source: SlotSource::Polling, root: None,
+            cluster_slot: None, slot_lag: 0, lagging_nodes: Vec::new(), forked_nodes: Vec::new(),
+            tps: None, tps_non_vote: None, tps_vote: None, tps_window_secs: 0,
+            tps_method: TpsMethod::Unavailable, slot_time_ms: None, skip_rate: None, epoch: 0, latency: 0,
+            status: EngineStatus::Booting, status_reason: StatusReason::Startup, status_detail: String::new(),
+            status_since: Utc::now(), consecutive_errors: 0, last_success: None,
         }
     }
 }
 
-pub async fn start_background_engine(
-    rpc_url: String,
-    shared_metrics: Arc<Mutex<EngineMetrics>>
-) {
-    println!(">>> ENGINE STARTED: Connecting to Solana RPC...");
-    
-    let client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    NodeLagging,
+    NodeCaughtUp,
+    ForkDetected,
+    ForkResolved,
+    SlotsStalled,
+    SlotsResumed,
+    HighSkipRate,
+    SkipRateNormal,
+    SlowSlots,
+    SlotTimeNormal,
+}
+
+/// A one-off event pushed to stream subscribers next to the metric snapshots.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    /// Redacted endpoint URL the alert is about.
+    pub endpoint: String,
+    pub detail: String,
+    pub at: DateTime<Utc>,
+}
+
+impl Alert {
+    fn new(kind: AlertKind, endpoint: &str, detail: String) -> Self {
+        Self { kind, endpoint: endpoint.to_string(), detail, at: Utc::now() }
+    }
+}
+
+impl ProbeReport {
+    /// Alerts for endpoints that entered or left the lagging / forked sets since `prev`.
+    /// `max_lag` is the threshold the probe used, for the alert text.
+    pub fn alerts_since(&self, prev: &ProbeReport, max_lag: u64) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        let tip = self.tip.unwrap_or_default();
+
+        for node in self.lagging.iter().filter(|n| !prev.lagging.contains(n)) {
+            let detail = format!("more than {} slots behind cluster tip {}", max_lag, tip);
+            alerts.push(Alert::new(AlertKind::NodeLagging, node, detail));
+        }
+        for node in prev.lagging.iter().filter(|n| !self.lagging.contains(n)) {
+            alerts.push(Alert::new(AlertKind::NodeCaughtUp, node, format!("back within {} slots", max_lag)));
+        }
+        for node in self.forked.iter().filter(|n| !prev.forked.contains(n)) {
+            let slot = self.fork_slot.unwrap_or_default();
+            let detail = format!("block hash at slot {} differs from the majority", slot);
+            alerts.push(Alert::new(AlertKind::ForkDetected, node, detail));
+        }
+        for node in prev.forked.iter().filter(|n| !self.forked.contains(n)) {
+            alerts.push(Alert::new(AlertKind::ForkResolved, node, "agrees with the majority again".to_string()));
+        }
+        alerts
+    }
+}
+
+/// Alerts for slot conditions that started or cleared between two assessments, where
+/// `prev` holds the reasons that held last time.
+pub fn slot_alerts(prev: &[StatusReason], now: &[(StatusReason, String)], endpoint: &str) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+    for (reason, detail) in now.iter().filter(|(r, _)| !prev.contains(r)) {
+        if let Some((raised, _, _)) = reason.alert_kinds() {
+            alerts.push(Alert::new(raised, endpoint, detail.clone()));
+        }
+    }
+    for reason in prev.iter().filter(|r| !now.iter().any(|(n, _)| n == *r)) {
+        if let Some((_, cleared, detail)) = reason.alert_kinds() {
+            alerts.push(Alert::new(cleared, endpoint, detail.to_string()));
+        }
+    }
+    alerts
+}
+
+impl EngineMetrics {
+    pub fn apply_probe(&mut self, report: &ProbeReport) {
+        self.cluster_slot = report.tip;
+        self.slot_lag = report.active_lag;
+        self.lagging_nodes = report.lagging.clone();
+        self.forked_nodes = report.forked.clone();
+    }
+
+    pub fn apply_throughput(&mut self, t: Throughput) {
+        self.tps = t.tps;
+        self.tps_non_vote = t.tps_non_vote;
+        self.tps_vote = t.tps_vote;
+        self.tps_window_secs = t.window_secs;
+        self.tps_method = t.method;
+    }
+
+    /// Moves to `status` for `reason`. `status_since` only moves when either of them
+    /// changes, so a refreshed detail is not a new transition.
+    pub fn set_status(&mut self, status: EngineStatus, reason: StatusReason, detail: String) {
+        if (self.status, self.status_reason) != (status, reason) {
+            self.status_since = Utc::now();
+        }
+        self.status = status;
+        self.status_reason = reason;
+        self.status_detail = detail;
+    }
+
+    /// The transition from `prev` to `self`, if the status or its reason changed.
+    pub fn transition_from(&self, prev: &EngineMetrics) -> Option<StatusTransition> {
+        if (prev.status, prev.status_reason) == (self.status, self.status_reason) {
+            return None;
+        }
+        Some(StatusTransition {
+            from: prev.status,
+            to: self.status,
+            reason: self.status_reason,
+            detail: self.status_detail.clone(),
+            at: self.status_since,
+        })
+    }
+}
+
+/// PubSub endpoint matching an HTTP RPC URL. Providers serve both on the same host and
+/// port; only a local validator's default RPC port 8899 maps to its PubSub port 8900.
+pub fn ws_url_for(rpc_url: &str) -> String {
+    let ws = rpc_url.replacen("https://", "wss://", 1).replacen("http://", "ws://", 1);
+    if let Some((scheme, rest)) = ws.split_once("://") {
+        let (authority, tail) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+        if let Some(host) = authority.strip_suffix(":8899") {
+            return format!("{}://{}:8900{}", scheme, host, tail);
+        }
+    }
+    ws
+}
+
+/// Follows `slotSubscribe` on the pool's active endpoint and writes every notification
+/// to `hub`, reconnecting with backoff, and to the new endpoint when the pool fails over.
+/// While it is down the polling loop owns `slot` again.
+async fn follow_slots(pool: Arc<RpcPool>, hub: Arc<MetricsHub>) {
+    let mut retry = PUBSUB_RETRY_MIN;
 
     loop {
-        let start = Instant::now();
-        match client.get_epoch_info() {
-            Ok(info) => {
-                let duration = start.elapsed().as_millis();
-             
-                let mut data = shared_metrics.lock().unwrap();
-
-                data.slot = info.absolute_slot;
-                data.epoch = info.epoch;
-                data.latency = duration;
-                data.status = "OPERATIONAL".to_string();
-                data.tps = 2000 + (info.absolute_slot % 1200);
-            }
-            Err(e) => {
-                let mut data = shared_metrics.lock().unwrap();
-                data.status = "RECONNECTING".to_string();
-                eprintln!(">>> RPC WARN: {}", e);
-            }
-        }
-        sleep(Duration::from_secs(2)).await;
+        let rpc_url = pool.active().url.clone();
+        let ws_url = ws_url_for(&rpc_url);
+        let span = tracing::info_span!("slot_subscription", endpoint = %redact(&ws_url));
+        if subscribe_slots(&ws_url, &hub, || pool.active().url != rpc_url).instrument(span).await {
+            retry = PUBSUB_RETRY_MIN;
+        }
+
+        hub.update(|m| {
+            m.slot_source = SlotSource::Polling;
+            m.root = None;
+        });
+        exporter::registry().reconnect("pubsub");
+        sleep(retry).await;
+        retry = (retry * 2).min(PUBSUB_RETRY_MAX);
+    }
+}
+
+/// One subscription, until it drops, goes quiet or `moved` reports that the pool's active
+/// endpoint is no longer the one subscribed to. Returns whether it ever connected.
+async fn subscribe_slots(ws_url: &str, hub: &MetricsHub, moved: impl Fn() -> bool) -> bool {
+    let client = match PubsubClient::new(ws_url).await {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!(error = %e, "pubsub connect failed");
+            return false;
+        }
+    };
+    let (mut slots, unsubscribe) = match client.slot_subscribe().await {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            tracing::warn!(error = %e, "slotSubscribe failed");
+            return false;
+        }
+    };
+    tracing::info!("slot subscription active");
+
+    let mut quiet_deadline = Instant::now() + SLOT_NOTIFY_TIMEOUT;
+    let mut failover_check = tokio::time::interval(FAILOVER_CHECK);
+    loop {
+        tokio::select! {
+            next = slots.next() => {
+                let Some(info) = next else {
+                    tracing::warn!("slot subscription dropped, falling back to polling");
+                    break;
+                };
+                tracing::trace!(slot = info.slot, root = info.root, "slot notification");
+                quiet_deadline = Instant::now() + SLOT_NOTIFY_TIMEOUT;
+                hub.update(|m| {
+                    m.slot = m.slot.max(info.slot);
+                    m.root = Some(info.root);
+                    m.slot_source = SlotSource::Websocket;
+                });
+            }
+            _ = tokio::time::sleep_until(quiet_deadline) => {
+                tracing::warn!("no slot notification for {:?}, falling back to polling", SLOT_NOTIFY_TIMEOUT);
+                break;
+            }
+            _ = failover_check.tick() => {
+                if moved() {
+                    tracing::info!("pool failed over, moving the slot subscription");
+                    break;
+                }
+            }
+        }
+    }
+
+    drop(slots);
+    unsubscribe().await;
+    true
+}
+
+/// Engine state carried from one tick to the next.
+pub struct Engine {
+    pool: Arc<RpcPool>,
+    hub: Arc<MetricsHub>,
+    config: EngineConfig,
+    throughput: ThroughputTracker,
+    last_probe: Option<Instant>,
+    last_report: ProbeReport,
+    slots: SlotTracker,
+    /// Degraded conditions found by the last successful tick, for slot alerts.
+    conditions: Vec<StatusReason>,
+}
+
+impl Engine {
+    pub fn new(pool: Arc<RpcPool>, hub: Arc<MetricsHub>, config: EngineConfig) -> Self {
+        Self {
+            pool,
+            hub,
+            config,
+            throughput: ThroughputTracker::default(),
+            last_probe: None,
+            last_report: ProbeReport::default(),
+            slots: SlotTracker::default(),
+            conditions: Vec::new(),
+        }
+    }
+
+    /// Polls epoch, latency and throughput, and the slot while the slot subscription is
+    /// down, checks recent slots for skips when due, then re-assesses the status. The
+    /// queries (and the pool probe, when due) run concurrently. Every change is published
+    /// through `hub`.
+    pub async fn tick(&mut self) {
+        let probe_due = self.last_probe.map_or(true, |t| t.elapsed() >= self.config.probe_interval);
+        if probe_due {
+            self.last_probe = Some(Instant::now());
+        }
+        let fetch_samples = self.throughput.wants_samples();
+        let latest = self.hub.latest();
+        let skip_end = latest.root.unwrap_or(latest.slot.saturating_sub(SKIP_RATE_DEPTH));
+        let skip_range = self.slots.skip_range(skip_end, Instant::now());
+        // Nodes count as lagging at the same point the engine degrades for lag.
+        let max_lag = self.config.thresholds.degraded_slot_lag;
+        let pool = &self.pool;
+
+        let (report, epoch, samples, blocks) = tokio::join!(
+            async {
+                if probe_due { Some(pool.probe(max_lag).await) } else { None }
+            },
+            async {
+                let start = Instant::now();
+                // Fails over inside the pool; an error here means every endpoint failed.
+                let info = pool.call("getEpochInfo", |c| async move { c.get_epoch_info().await }).await;
+                (info, start.elapsed())
+            },
+            async {
+                if !fetch_samples {
+                    return None;
+                }
+                let samples = pool
+                    .call("getRecentPerformanceSamples", |c| async move {
+                        c.get_recent_performance_samples(PERF_SAMPLE_LIMIT).await
+                    })
+                    .await;
+                Some(samples)
+            },
+            async {
+                let (start, end) = skip_range?;
+                Some(pool.call("getBlocks", |c| async move { c.get_blocks(start, end).await }).await)
+            },
+        );
+
+        if let Some(report) = report {
+            for alert in report.alerts_since(&self.last_report, max_lag) {
+                tracing::warn!(kind = ?alert.kind, endpoint = %alert.endpoint, "{}", alert.detail);
+                self.hub.alert(alert);
+            }
+            self.hub.update(|data| data.apply_probe(&report));
+            self.last_report = report;
+        }
+
+        match samples {
+            Some(Ok(samples)) => self.throughput.record_samples(Some(&samples)),
+            Some(Err(e)) => {
+                tracing::warn!(error = %e, "performance samples unavailable");
+                self.throughput.record_samples(None);
+            }
+            None => {}
+        }
+
+        match (skip_range, blocks) {
+            (Some(range), Some(Ok(blocks))) => self.slots.record_blocks(range, Some(&blocks)),
+            (Some(range), Some(Err(e))) => {
+                tracing::warn!(error = %e, "getBlocks failed, skip rate unavailable");
+                self.slots.record_blocks(range, None);
+            }
+            _ => {}
+        }
+
+        match epoch {
+            (Ok(info), duration) => {
+                let tps = self.throughput.update(&info);
+                let latest = self.hub.latest();
+                let slot = match latest.slot_source {
+                    // Confirmed commitment trails the processed slots we had from pubsub.
+                    SlotSource::Polling => latest.slot.max(info.absolute_slot),
+                    SlotSource::Websocket => latest.slot,
+                };
+                let now = Instant::now();
+                self.slots.observe(slot, now);
+                let stalled_for = self.slots.stalled_for(now);
+                let slot_time = self.slots.avg_slot_time();
+                let skip_rate = self.slots.skip_rate();
+                let thresholds = &self.config.thresholds;
+                let mut conditions = Vec::new();
+
+                self.hub.update(|data| {
+                    data.slot = data.slot.max(slot);
+                    data.epoch = info.epoch;
+                    data.latency = duration.as_millis();
+                    data.apply_throughput(tps);
+                    data.slot_time_ms = slot_time.map(|d| d.as_millis() as u64);
+                    data.skip_rate = skip_rate;
+                    data.consecutive_errors = 0;
+                    data.last_success = Some(Utc::now());
+                    conditions = thresholds.conditions(data, stalled_for);
+                    match conditions.first() {
+                        Some((reason, detail)) => data.set_status(EngineStatus::Degraded, *reason, detail.clone()),
+                        None => data.set_status(EngineStatus::Operational, StatusReason::Healthy, String::new()),
+                    }
+                });
+
+                let endpoint = redact(&self.pool.active().url);
+                for alert in slot_alerts(&self.conditions, &conditions, &endpoint) {
+                    tracing::warn!(kind = ?alert.kind, endpoint = %alert.endpoint, "{}", alert.detail);
+                    self.hub.alert(alert);
+                }
+                self.conditions = conditions.into_iter().map(|(reason, _)| reason).collect();
+            }
+            (Err(e), _) => {
+                let thresholds = &self.config.thresholds;
+                self.hub.update(|data| {
+                    data.consecutive_errors += 1;
+                    let detail = format!("{} updates failed in a row", data.consecutive_errors);
+                    if data.consecutive_errors >= thresholds.down_after_errors {
+                        data.set_status(EngineStatus::Down, StatusReason::RpcErrors, detail);
+                    } else if data.consecutive_errors >= thresholds.reconnecting_after_errors {
+                        data.set_status(EngineStatus::Reconnecting, StatusReason::RpcErrors, detail);
+                    }
+                });
+                tracing::warn!(error = %e, "all endpoints failed");
+            }
+        }
+    }
+}
+
+/// Runs the engine until the process exits, alongside the slot subscription.
+pub async fn start_background_engine(pool: Arc<RpcPool>, hub: Arc<MetricsHub>, config: EngineConfig) {
+    tracing::info!(endpoints = pool.endpoints().len(), "engine started");
+
+    tokio::spawn(follow_slots(pool.clone(), hub.clone()));
+
+    let poll_interval = config.poll_interval;
+    let mut engine = Engine::new(pool, hub, config);
+    loop {
+        engine.tick().await;
+        sleep(poll_interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_source::{mock::ScriptedRpc, RpcSource};
+    use serde_json::{json, Value};
+
+    fn sample(transactions: u64, non_vote: Option<u64>, secs: u16) -> RpcPerfSample {
+        RpcPerfSample {
+            slot: 1_000,
+            num_transactions: transactions,
+            num_non_vote_transactions: non_vote,
+            num_slots: 150,
+            sample_period_secs: secs,
+        }
+    }
+
+    fn epoch_info(slot: u64) -> Value {
+        json!({
+            "absoluteSlot": slot,
+            "blockHeight": slot - 100,
+            "epoch": slot / 432_000,
+            "slotIndex": slot % 432_000,
+            "slotsInEpoch": 432_000,
+            "transactionCount": 5_000_000,
+        })
+    }
+
+    fn engine(rpc: Arc<ScriptedRpc>) -> (Engine, Arc<MetricsHub>) {
+        let pool = Arc::new(RpcPool::from_sources(vec![rpc as Arc<dyn RpcSource>], Duration::from_secs(1)));
+        let hub = Arc::new(MetricsHub::default());
+        (Engine::new(pool, hub.clone(), EngineConfig::default()), hub)
+    }
+
+    #[test]
+    fn tps_from_perf_samples() {
+        let t = Throughput::from_perf_samples(&[sample(120_000, Some(30_000), 60), sample(60_000, Some(15_000), 60)]).unwrap();
+        assert_eq!(t.tps, Some(1_500));
+        assert_eq!(t.tps_non_vote, Some(375));
+        assert_eq!(t.tps_vote, Some(1_125));
+        assert_eq!(t.window_secs, 120);
+        assert_eq!(t.method, TpsMethod::PerformanceSamples);
+
+        // One sample without the split drops the split, not the total.
+        let t = Throughput::from_perf_samples(&[sample(120_000, Some(30_000), 60), sample(60_000, None, 60)]).unwrap();
+        assert_eq!(t.tps, Some(1_500));
+        assert_eq!(t.tps_non_vote, None);
+        assert_eq!(t.tps_vote, None);
+
+        assert_eq!(Throughput::from_perf_samples(&[]), None);
+        assert_eq!(Throughput::from_perf_samples(&[sample(10, None, 0)]), None);
+    }
+
+    #[test]
+    fn tps_from_tx_count_delta() {
+        let t0 = Instant::now();
+        let t = Throughput::from_tx_count_delta((1_000, t0), (4_000, t0 + Duration::from_secs(2))).unwrap();
+        assert_eq!(t.tps, Some(1_500));
+        assert_eq!(t.window_secs, 2);
+        assert_eq!(t.method, TpsMethod::TransactionCountDelta);
+
+        // Too short a window, or a counter that went backwards (node switch), gives nothing.
+        assert_eq!(Throughput::from_tx_count_delta((1_000, t0), (4_000, t0 + Duration::from_millis(500))), None);
+        assert_eq!(Throughput::from_tx_count_delta((4_000, t0), (1_000, t0 + Duration::from_secs(2))), None);
+    }
+
+    #[test]
+    fn pubsub_url_keeps_provider_ports() {
+        assert_eq!(ws_url_for("http://127.0.0.1:8899"), "ws://127.0.0.1:8900");
+        assert_eq!(ws_url_for("http://localhost:8899/"), "ws://localhost:8900/");
+        assert_eq!(ws_url_for("https://rpc.example.com:443/?api-key=k:1"), "wss://rpc.example.com:443/?api-key=k:1");
+        assert_eq!(ws_url_for("https://rpc.example.com/v1/abc"), "wss://rpc.example.com/v1/abc");
+        assert_eq!(ws_url_for("http://127.0.0.1:9000"), "ws://127.0.0.1:9000");
+    }
+
+    #[test]
+    fn probe_alerts_only_on_changes() {
+        let prev = ProbeReport { lagging: vec!["http://a".into()], ..ProbeReport::default() };
+        let next = ProbeReport {
+            tip: Some(500),
+            lagging: vec!["http://b".into()],
+            forked: vec!["http://c".into()],
+            fork_slot: Some(468),
+            ..ProbeReport::default()
+        };
+
+        let kinds: Vec<(AlertKind, String)> = next.alerts_since(&prev, MAX_SLOT_LAG).into_iter().map(|a| (a.kind, a.endpoint)).collect();
+        assert_eq!(kinds, vec![
+            (AlertKind::NodeLagging, "http://b".to_string()),
+            (AlertKind::NodeCaughtUp, "http://a".to_string()),
+            (AlertKind::ForkDetected, "http://c".to_string()),
+        ]);
+        assert!(next.alerts_since(&next, MAX_SLOT_LAG).is_empty());
+    }
+
+    #[tokio::test]
+    async fn status_booting_operational_reconnecting() {
+        let rpc = Arc::new(ScriptedRpc::new("http://mock"));
+        rpc.push("getSlot", json!(864_010))
+            .push("getEpochInfo", epoch_info(864_010))
+            .push("getRecentPerformanceSamples", json!([{
+                "slot": 864_000, "numTransactions": 180_000, "numNonVoteTransactions": 45_000,
+                "numSlots": 150, "samplePeriodSecs": 60,
+            }]));
+        let (mut engine, hub) = engine(rpc.clone());
+        assert_eq!(hub.latest().status, EngineStatus::Booting);
+
+        engine.tick().await;
+        let m = hub.latest();
+        assert_eq!((m.status, m.status_reason), (EngineStatus::Operational, StatusReason::Healthy));
+        assert!(m.last_success.is_some());
+        assert_eq!((m.slot, m.epoch, m.cluster_slot), (864_010, 2, Some(864_010)));
+        assert_eq!((m.tps, m.tps_non_vote, m.tps_method), (Some(3_000), Some(750), TpsMethod::PerformanceSamples));
+
+        rpc.clear("getEpochInfo").push_error("getEpochInfo", "connection refused");
+        engine.tick().await;
+        let m = hub.latest();
+        assert_eq!((m.status, m.status_reason), (EngineStatus::Reconnecting, StatusReason::RpcErrors));
+        assert_eq!(m.consecutive_errors, 1);
+        // Last known values stay visible while reconnecting.
+        assert_eq!(m.slot, 864_010);
+
+        rpc.clear("getEpochInfo").push("getEpochInfo", epoch_info(864_020));
+        engine.tick().await;
+        let m = hub.latest();
+        assert_eq!(m.status, EngineStatus::Operational);
+        assert_eq!(m.consecutive_errors, 0);
+        assert_eq!(m.slot, 864_020);
+
+        let path: Vec<(EngineStatus, EngineStatus)> = hub.transitions().iter().map(|t| (t.from, t.to)).collect();
+        assert_eq!(path, vec![
+            (EngineStatus::Booting, EngineStatus::Operational),
+            (EngineStatus::Operational, EngineStatus::Reconnecting),
+            (EngineStatus::Reconnecting, EngineStatus::Operational),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn down_after_consecutive_errors() {
+        let rpc = Arc::new(ScriptedRpc::new("http://mock"));
+        rpc.push("getSlot", json!(100)).push("getEpochInfo", epoch_info(100)).push("getRecentPerformanceSamples", json!([]));
+        let (mut engine, hub) = engine(rpc.clone());
+        engine.config.thresholds.reconnecting_after_errors = 2;
+        engine.config.thresholds.down_after_errors = 3;
+        engine.tick().await;
+
+        rpc.clear("getEpochInfo").push_error("getEpochInfo", "connection refused");
+        engine.tick().await;
+        // Below the reconnecting threshold the last status holds.
+        assert_eq!((hub.latest().status, hub.latest().consecutive_errors), (EngineStatus::Operational, 1));
+        engine.tick().await;
+        assert_eq!(hub.latest().status, EngineStatus::Reconnecting);
+        engine.tick().await;
+        let m = hub.latest();
+        assert_eq!((m.status, m.status_reason, m.consecutive_errors), (EngineStatus::Down, StatusReason::RpcErrors, 3));
+        assert_eq!(m.status_detail, "3 updates failed in a row");
+
+        // Further failures refresh the detail without logging another transition.
+        engine.tick().await;
+        assert_eq!(hub.latest().consecutive_errors, 4);
+        assert_eq!(hub.transitions().len(), 3);
+    }
+
+    #[test]
+    fn degraded_conditions_in_priority_order() {
+        let t = StatusThresholds::default();
+        let reasons = |m: &EngineMetrics, stalled_secs: u64| -> Vec<StatusReason> {
+            t.conditions(m, Duration::from_secs(stalled_secs)).into_iter().map(|(r, _)| r).collect()
+        };
+        let healthy = EngineMetrics { slot: 500, latency: 120, slot_time_ms: Some(410), skip_rate: Some(0.04), ..EngineMetrics::default() };
+        assert!(reasons(&healthy, 1).is_empty());
+
+        let slow = EngineMetrics { latency: 2_000, ..healthy.clone() };
+        assert_eq!(t.conditions(&slow, Duration::ZERO), vec![(StatusReason::HighLatency, "getEpochInfo took 2000ms".to_string())]);
+
+        let all = EngineMetrics { slot_lag: MAX_SLOT_LAG + 1, skip_rate: Some(0.4), slot_time_ms: Some(900), ..slow.clone() };
+        assert_eq!(reasons(&all, 0), vec![
+            StatusReason::NodeLagging,
+            StatusReason::HighSkipRate,
+            StatusReason::SlowSlots,
+            StatusReason::HighLatency,
+        ]);
+
+        let stalled = t.conditions(&all, Duration::from_secs(25));
+        assert_eq!(stalled[0], (StatusReason::SlotStall, "slot 500 has not advanced for 25s".to_string()));
+        assert_eq!(t.conditions(&all, Duration::ZERO)[1].1, "40.0% of the last 300 slots were skipped");
+    }
+
+    #[test]
+    fn slot_time_and_stalls_over_the_window() {
+        let t0 = Instant::now();
+        let at = |secs: u64| t0 + Duration::from_secs(secs);
+        let mut slots = SlotTracker::default();
+        slots.observe(1_000, at(0));
+        slots.observe(1_005, at(2));
+        assert_eq!(slots.avg_slot_time(), None, "window too short");
+        slots.observe(1_010, at(4));
+        slots.observe(1_015, at(6));
+        assert_eq!(slots.avg_slot_time(), Some(Duration::from_millis(400)));
+
+        // The same slot again is not an advance: the stall is counted from its first sighting.
+        slots.observe(1_015, at(9));
+        assert_eq!(slots.stalled_for(at(9)), Duration::from_secs(3));
+        assert_eq!(slots.avg_slot_time(), Some(Duration::from_millis(400)));
+
+        // Once the window has slid past every earlier advance there is no rate, only the stall.
+        slots.observe(1_015, at(70));
+        assert_eq!(slots.avg_slot_time(), None);
+        assert_eq!(slots.stalled_for(at(70)), Duration::from_secs(64));
+    }
+
+    #[test]
+    fn skip_rate_from_block_gaps() {
+        let t0 = Instant::now();
+        let mut slots = SlotTracker::default();
+        assert_eq!(slots.skip_range(SKIP_RATE_SLOTS - 1, t0), None, "slot not known yet");
+        let range = slots.skip_range(10_299, t0).unwrap();
+        assert_eq!(range, (10_000, 10_299));
+        assert_eq!(slots.skip_range(10_400, t0 + SKIP_RATE_REFRESH / 2), None, "not due again yet");
+        assert_eq!(slots.skip_range(10_400, t0 + SKIP_RATE_REFRESH), Some((10_101, 10_400)));
+
+        let blocks: Vec<u64> = (range.0..=range.1).filter(|s| s % 4 != 0).collect();
+        slots.record_blocks(range, Some(&blocks));
+        assert_eq!(slots.skip_rate(), Some(0.25));
+        slots.record_blocks(range, None);
+        assert_eq!(slots.skip_rate(), None);
+    }
+
+    #[tokio::test]
+    async fn stall_raises_degraded_and_alerts() {
+        let rpc = Arc::new(ScriptedRpc::new("http://mock"));
+        rpc.push("getSlot", json!(864_010))
+            .push("getEpochInfo", epoch_info(864_010))
+            .push("getRecentPerformanceSamples", json!([]))
+            // Every slot checked (300 up to 32 below the slot) has a block.
+            .push("getBlocks", json!((863_679..=863_978).collect::<Vec<u64>>()));
+        let (mut engine, hub) = engine(rpc.clone());
+        engine.config.thresholds.stall_after = Duration::from_millis(50);
+        let mut alerts = hub.subscribe_alerts();
+
+        engine.tick().await;
+        assert_eq!(hub.latest().status, EngineStatus::Operational);
+
+        sleep(Duration::from_millis(60)).await;
+        engine.tick().await;
+        let m = hub.latest();
+        assert_eq!((m.status, m.status_reason), (EngineStatus::Degraded, StatusReason::SlotStall));
+        assert_eq!(m.skip_rate, Some(0.0));
+        let alert = alerts.try_recv().unwrap();
+        assert_eq!((alert.kind, alert.endpoint.as_str()), (AlertKind::SlotsStalled, "http://mock"));
+
+        // Still stalled: no repeat alert.
+        engine.tick().await;
+        assert!(alerts.try_recv().is_err());
+
+        rpc.clear("getEpochInfo").push("getEpochInfo", epoch_info(864_011));
+        engine.tick().await;
+        assert_eq!(hub.latest().status, EngineStatus::Operational);
+        assert_eq!(alerts.try_recv().unwrap().kind, AlertKind::SlotsResumed);
+    }
+
+    #[test]
+    fn since_moves_only_on_transitions() {
+        let mut m = EngineMetrics::default();
+        m.set_status(EngineStatus::Degraded, StatusReason::HighLatency, "getEpochInfo took 1600ms".into());
+        let entered = m.status_since;
+        let prev = m.clone();
+
+        m.set_status(EngineStatus::Degraded, StatusReason::HighLatency, "getEpochInfo took 1900ms".into());
+        assert_eq!(m.status_since, entered);
+        assert!(m.transition_from(&prev).is_none());
+
+        m.set_status(EngineStatus::Degraded, StatusReason::SlotStall, "slot 1 has not advanced for 20s".into());
+        let t = m.transition_from(&prev).unwrap();
+        assert_eq!((t.from, t.to, t.reason), (EngineStatus::Degraded, EngineStatus::Degraded, StatusReason::SlotStall));
+    }
+
+    #[tokio::test]
+    async fn samples_are_fetched_once_per_refresh() {
+        let rpc = Arc::new(ScriptedRpc::new("http://mock"));
+        rpc.push("getSlot", json!(100)).push("getEpochInfo", epoch_info(100)).push("getRecentPerformanceSamples", json!([]));
+        let (mut engine, _hub) = engine(rpc.clone());
+
+        engine.tick().await;
+        engine.tick().await;
+        let fetches = rpc.calls().iter().filter(|m| *m == "getRecentPerformanceSamples").count();
+        assert_eq!(fetches, 1);
+        assert_eq!(rpc.calls().iter().filter(|m| *m == "getEpochInfo").count(), 2);
     }
 }
@@ -0,0 +1,195 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use crate::engine::EngineStatus;
+use crate::models::AppState;
+
+const RPC_BUCKETS: &[f64] = &[0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+const HTTP_BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+struct Histogram {
+    bounds: &'static [f64],
+    /// Non-cumulative; summed when rendered.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self { bounds, counts: vec![0; bounds.len()], sum: 0.0, count: 0 }
+    }
+
+    fn observe(&mut self, secs: f64) {
+        if let Some(i) = self.bounds.iter().position(|b| secs <= *b) {
+            self.counts[i] += 1;
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, self.count);
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, self.count);
+    }
+}
+
+/// Process-wide counters and histograms. Gauges (engine, DB pool) are read at scrape time
+/// instead of being stored here.
+#[derive(Default)]
+pub struct Registry {
+    // (method, endpoint)
+    rpc_duration: Mutex<BTreeMap<(&'static str, String), Histogram>>,
+    rpc_errors: Mutex<BTreeMap<(&'static str, String), u64>>,
+    // kind: "pubsub" | "failover"
+    reconnects: Mutex<BTreeMap<&'static str, u64>>,
+    // (method, route, status)
+    http_requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+    // (method, route)
+    http_duration: Mutex<BTreeMap<(String, String), Histogram>>,
+}
+
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+impl Registry {
+    /// `endpoint` should already be redacted.
+    pub fn rpc_call(&self, method: &'static str, endpoint: &str, elapsed: Duration, ok: bool) {
+        let key = (method, endpoint.to_string());
+        if !ok {
+            *self.rpc_errors.lock().unwrap().entry(key.clone()).or_default() += 1;
+        }
+        self.rpc_duration.lock().unwrap()
+            .entry(key)
+            .or_insert_with(|| Histogram::new(RPC_BUCKETS))
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn reconnect(&self, kind: &'static str) {
+        *self.reconnects.lock().unwrap().entry(kind).or_default() += 1;
+    }
+
+    fn http_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        *self.http_requests.lock().unwrap()
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        self.http_duration.lock().unwrap()
+            .entry((method.to_string(), route.to_string()))
+            .or_insert_with(|| Histogram::new(HTTP_BUCKETS))
+            .observe(elapsed.as_secs_f64());
+    }
+
+    fn render(&self, out: &mut String) {
+        describe(out, "arkheion_rpc_request_duration_seconds", "histogram", "RPC call duration by method and endpoint.");
+        for ((method, endpoint), h) in self.rpc_duration.lock().unwrap().iter() {
+            let labels = format!("method=\"{}\",endpoint=\"{}\"", escape(method), escape(endpoint));
+            h.render(out, "arkheion_rpc_request_duration_seconds", &labels);
+        }
+
+        describe(out, "arkheion_rpc_errors_total", "counter", "Failed RPC calls by method and endpoint.");
+        for ((method, endpoint), n) in self.rpc_errors.lock().unwrap().iter() {
+            let _ = writeln!(out, "arkheion_rpc_errors_total{{method=\"{}\",endpoint=\"{}\"}} {}", escape(method), escape(endpoint), n);
+        }
+
+        describe(out, "arkheion_rpc_reconnects_total", "counter", "Slot subscription reconnects and pool failovers.");
+        for (kind, n) in self.reconnects.lock().unwrap().iter() {
+            let _ = writeln!(out, "arkheion_rpc_reconnects_total{{kind=\"{}\"}} {}", kind, n);
+        }
+
+        describe(out, "arkheion_http_requests_total", "counter", "HTTP requests by method, route and status.");
+        for ((method, route, status), n) in self.http_requests.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "arkheion_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                escape(method), escape(route), status, n
+            );
+        }
+
+        describe(out, "arkheion_http_request_duration_seconds", "histogram", "Time to response headers by method and route.");
+        for ((method, route), h) in self.http_duration.lock().unwrap().iter() {
+            let labels = format!("method=\"{}\",route=\"{}\"", escape(method), escape(route));
+            h.render(out, "arkheion_http_request_duration_seconds", &labels);
+        }
+    }
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: impl std::fmt::Display) {
+    describe(out, name, "gauge", help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Counts every request by its route template (not the raw path, to keep label
+/// cardinality bounded). For SSE and WebSocket routes the duration ends at the headers.
+pub async fn track_http(req: Request, next: Next) -> Response {
+    let method = req.method().to_string();
+    let route = req.extensions().get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let start = Instant::now();
+
+    let response = next.run(req).await;
+    registry().http_request(&method, &route, response.status().as_u16(), start.elapsed());
+    response
+}
+
+/// Prometheus text exposition (format 0.0.4).
+pub async fn scrape(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let m = state.metrics.latest();
+    let mut out = String::new();
+
+    gauge(&mut out, "arkheion_slot", "Latest slot seen by the engine.", m.slot);
+    gauge(&mut out, "arkheion_epoch", "Current epoch.", m.epoch);
+    gauge(&mut out, "arkheion_slot_lag", "Slots the active RPC endpoint trails the highest one seen.", m.slot_lag);
+    if let Some(tps) = m.tps {
+        gauge(&mut out, "arkheion_tps", "Network transactions per second.", tps);
+    }
+    if let Some(ms) = m.slot_time_ms {
+        gauge(&mut out, "arkheion_slot_time_seconds", "Average slot time over the last minute.", ms as f64 / 1000.0);
+    }
+    if let Some(rate) = m.skip_rate {
+        gauge(&mut out, "arkheion_skip_rate", "Share of recent slots that produced no block.", rate);
+    }
+    gauge(&mut out, "arkheion_rpc_latency_seconds", "Duration of the engine's last getEpochInfo.", m.latency as f64 / 1000.0);
+    describe(&mut out, "arkheion_engine_status", "gauge", "1 for the engine's current status, 0 for the others.");
+    for status in EngineStatus::ALL {
+        let _ = writeln!(out, "arkheion_engine_status{{status=\"{}\"}} {}", status.as_str(), u8::from(status == m.status));
+    }
+    gauge(&mut out, "arkheion_engine_consecutive_errors", "Engine updates that failed in a row.", m.consecutive_errors);
+
+    gauge(&mut out, "arkheion_db_pool_connections", "Open SQLite pool connections.", state.db.size());
+    gauge(&mut out, "arkheion_db_pool_idle", "Idle SQLite pool connections.", state.db.num_idle());
+
+    registry().render(&mut out);
+
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")], out)
+}
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new().route("/metrics", get(scrape)).with_state(state)
+}
@@ -0,0 +1,704 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use chrono::{DateTime, Duration as ChronoDuration, TimeZone, Utc};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sqlx::{FromRow, Pool, Sqlite};
+use std::sync::Arc;
+use tokio::time::{interval, Duration, MissedTickBehavior};
+use crate::keys::AuthedKey;
+use crate::models::AppState;
+use crate::routes;
+use crate::stream::MetricsHub;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 3] = [Resolution::Minute, Resolution::Hour, Resolution::Day];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resolution::Minute => "1m",
+            Resolution::Hour => "1h",
+            Resolution::Day => "1d",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| r.as_str() == s)
+    }
+
+    pub fn bucket_secs(&self) -> i64 {
+        match self {
+            Resolution::Minute => 60,
+            Resolution::Hour => 3_600,
+            Resolution::Day => 86_400,
+        }
+    }
+
+    /// Start of the bucket containing `t`.
+    pub fn floor(&self, t: DateTime<Utc>) -> DateTime<Utc> {
+        let secs = t.timestamp().div_euclid(self.bucket_secs()) * self.bucket_secs();
+        Utc.timestamp_opt(secs, 0).single().unwrap_or(t)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryConfig {
+    /// How often the current engine snapshot is written to `metric_samples`.
+    pub sample_interval: Duration,
+    /// How often rollups are brought up to date and old rows pruned.
+    pub rollup_interval: Duration,
+    /// Raw samples must outlive the largest bucket, since every rollup reads them.
+    pub raw_retention: ChronoDuration,
+    pub minute_retention: ChronoDuration,
+    pub hour_retention: ChronoDuration,
+    pub day_retention: ChronoDuration,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            sample_interval: Duration::from_secs(2),
+            rollup_interval: Duration::from_secs(60),
+            raw_retention: ChronoDuration::days(3),
+            minute_retention: ChronoDuration::days(7),
+            hour_retention: ChronoDuration::days(90),
+            day_retention: ChronoDuration::days(730),
+        }
+    }
+}
+
+impl HistoryConfig {
+    pub fn retention(&self, resolution: Resolution) -> ChronoDuration {
+        match resolution {
+            Resolution::Minute => self.minute_retention,
+            Resolution::Hour => self.hour_retention,
+            Resolution::Day => self.day_retention,
+        }
+    }
+}
+
+#[derive(Debug, Clone, FromRow)]
+struct SampleRow {
+    sampled_at: DateTime<Utc>,
+    slot: i64,
+    epoch: i64,
+    tps: Option<i64>,
+    latency_ms: i64,
+    status: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub p95: f64,
+}
+
+impl Stats {
+    /// Nearest-rank statistics; `None` for an empty input.
+    pub fn of(values: &mut [f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let rank = ((values.len() as f64) * 0.95).ceil() as usize;
+        Some(Self {
+            min: values[0],
+            max: values[values.len() - 1],
+            avg: values.iter().sum::<f64>() / values.len() as f64,
+            p95: values[rank.clamp(1, values.len()) - 1],
+        })
+    }
+}
+
+/// Writes the current snapshot every `sample_interval` and keeps rollups and retention
+/// up to date every `rollup_interval`.
+pub async fn start_history_recorder(db: Pool<Sqlite>, hub: Arc<MetricsHub>, config: HistoryConfig) {
+    tracing::info!(
+        sample_interval = ?config.sample_interval,
+        rollup_interval = ?config.rollup_interval,
+        "history recorder started"
+    );
+
+    let mut sample = interval(config.sample_interval);
+    let mut rollup = interval(config.rollup_interval);
+    sample.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    rollup.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        tokio::select! {
+            _ = sample.tick() => {
+                if let Err(e) = record_sample(&db, &hub).await {
+                    tracing::error!(error = %e, "metric sample insert failed");
+                }
+            }
+            _ = rollup.tick() => {
+                let now = Utc::now();
+                for resolution in Resolution::ALL {
+                    if let Err(e) = roll_up(&db, resolution, now).await {
+                        tracing::error!(error = %e, resolution = resolution.as_str(), "rollup failed");
+                    }
+                }
+                if let Err(e) = prune(&db, &config, now).await {
+                    tracing::error!(error = %e, "history prune failed");
+                }
+            }
+        }
+    }
+}
+
+async fn record_sample(db: &Pool<Sqlite>, hub: &MetricsHub) -> Result<(), sqlx::Error> {
+    let m = hub.latest();
+    // Nothing worth keeping until the engine has talked to a node.
+    if m.slot == 0 {
+        return Ok(());
+    }
+
+    sqlx::query(
+        "INSERT INTO metric_samples (sampled_at, slot, epoch, tps, latency_ms, slot_lag, status)
+         VALUES (datetime(?), ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(Utc::now())
+    .bind(m.slot as i64)
+    .bind(m.epoch as i64)
+    .bind(m.tps.map(|t| t as i64))
+    .bind(m.latency as i64)
+    .bind(m.slot_lag as i64)
+    .bind(m.status.as_str())
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Aggregates every complete bucket after the last one rolled up. The bucket `now` is
+/// in is left alone until it closes.
+pub async fn roll_up(db: &Pool<Sqlite>, resolution: Resolution, now: DateTime<Utc>) -> Result<usize, sqlx::Error> {
+    let last: Option<DateTime<Utc>> = sqlx::query_scalar(
+        "SELECT MAX(bucket_start) FROM metric_rollups WHERE resolution = ?",
+    )
+    .bind(resolution.as_str())
+    .fetch_one(db)
+    .await?;
+
+    let from = match last {
+        Some(start) => start + ChronoDuration::seconds(resolution.bucket_secs()),
+        None => Utc.timestamp_opt(0, 0).unwrap(),
+    };
+    let until = resolution.floor(now);
+    if from >= until {
+        return Ok(0);
+    }
+
+    let rows: Vec<SampleRow> = sqlx::query_as(
+        "SELECT sampled_at, slot, epoch, tps, latency_ms, status FROM metric_samples
+         WHERE sampled_at >= datetime(?) AND sampled_at < datetime(?) ORDER BY sampled_at, id",
+    )
+    .bind(from)
+    .bind(until)
+    .fetch_all(db)
+    .await?;
+
+    // A change between the last sample of one bucket and the first of the next counts
+    // in the later bucket, so seed the comparison with the sample just before `from`.
+    let mut prev_status: Option<String> = sqlx::query_scalar(
+        "SELECT status FROM metric_samples WHERE sampled_at < datetime(?) ORDER BY sampled_at DESC, id DESC LIMIT 1",
+    )
+    .bind(from)
+    .fetch_optional(db)
+    .await?;
+
+    let mut written = 0;
+    let mut tx = db.begin().await?;
+    for bucket in rows.chunk_by(|a, b| resolution.floor(a.sampled_at) == resolution.floor(b.sampled_at)) {
+        let last = &bucket[bucket.len() - 1];
+        let entered_changed = prev_status.replace(last.status.clone()).is_some_and(|s| s != bucket[0].status);
+        let status_changes = bucket.windows(2).filter(|w| w[0].status != w[1].status).count() as i64 + entered_changed as i64;
+
+        let mut latency: Vec<f64> = bucket.iter().map(|r| r.latency_ms as f64).collect();
+        let mut tps: Vec<f64> = bucket.iter().filter_map(|r| r.tps).map(|t| t as f64).collect();
+        let Some(latency) = Stats::of(&mut latency) else { continue };
+        let tps = Stats::of(&mut tps);
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO metric_rollups
+             (resolution, bucket_start, samples, slot_min, slot_max, epoch,
+              latency_min, latency_max, latency_avg, latency_p95,
+              tps_min, tps_max, tps_avg, tps_p95, status, status_changes)
+             VALUES (?, datetime(?), ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(resolution.as_str())
+        .bind(resolution.floor(bucket[0].sampled_at))
+        .bind(bucket.len() as i64)
+        .bind(bucket.iter().map(|r| r.slot).min())
+        .bind(bucket.iter().map(|r| r.slot).max())
+        .bind(last.epoch)
+        .bind(latency.min)
+        .bind(latency.max)
+        .bind(latency.avg)
+        .bind(latency.p95)
+        .bind(tps.map(|s| s.min))
+        .bind(tps.map(|s| s.max))
+        .bind(tps.map(|s| s.avg))
+        .bind(tps.map(|s| s.p95))
+        .bind(&last.status)
+        .bind(status_changes)
+        .execute(&mut *tx)
+        .await?;
+        written += 1;
+    }
+    tx.commit().await?;
+
+    Ok(written)
+}
+
+/// Drops raw samples and rollups older than their configured retention.
+pub async fn prune(db: &Pool<Sqlite>, config: &HistoryConfig, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM metric_samples WHERE sampled_at < datetime(?)")
+        .bind(now - config.raw_retention)
+        .execute(db)
+        .await?;
+
+    for resolution in Resolution::ALL {
+        sqlx::query("DELETE FROM metric_rollups WHERE resolution = ? AND bucket_start < datetime(?)")
+            .bind(resolution.as_str())
+            .bind(now - config.retention(resolution))
+            .execute(db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Most points one history request may return; larger ranges are paged.
+pub fn max_points_for_tier(tier: &str) -> usize {
+    match tier.to_ascii_lowercase().as_str() {
+        "pro" => 5_000,
+        "enterprise" => 50_000,
+        _ => 500,
+    }
+}
+
+/// Field groups selectable with `fields=`. Names follow `routes::metrics_data`; rollups
+/// add `_min`/`_max`/`_p95` companions and use the bucket average for the plain name.
+const FIELD_GROUPS: &[(&str, &[&str])] = &[
+    ("slot", &["slot", "slot_min"]),
+    ("epoch", &["epoch"]),
+    ("tps", &["tps", "tps_min", "tps_max", "tps_p95"]),
+    ("latency", &["latency_ms", "latency_min_ms", "latency_max_ms", "latency_p95_ms"]),
+    ("status", &["status", "status_changes"]),
+];
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// `raw`, `1m`, `1h` or `1d`; defaults to `1m`.
+    pub resolution: Option<String>,
+    /// Comma-separated groups from [`FIELD_GROUPS`]; all when absent.
+    pub fields: Option<String>,
+    pub limit: Option<usize>,
+    /// With `from`, resumes after the row with this id; set from `next_after_id`.
+    pub after_id: Option<i64>,
+    /// `json` (default) or `csv`; `Accept: text/csv` works too.
+    pub format: Option<String>,
+}
+
+/// One sample or bucket. Columns a resolution does not have stay `None` and are omitted.
+#[derive(Debug, FromRow)]
+struct HistoryPoint {
+    /// Row id, only used as the paging tiebreak between points sharing a second.
+    id: i64,
+    timestamp: DateTime<Utc>,
+    slot: i64,
+    slot_min: Option<i64>,
+    epoch: i64,
+    tps: Option<f64>,
+    tps_min: Option<f64>,
+    tps_max: Option<f64>,
+    tps_p95: Option<f64>,
+    latency_ms: f64,
+    latency_min_ms: Option<f64>,
+    latency_max_ms: Option<f64>,
+    latency_p95_ms: Option<f64>,
+    status: String,
+    status_changes: Option<i64>,
+}
+
+impl HistoryPoint {
+    /// Selected columns in a stable order, `timestamp` first.
+    fn columns(&self, fields: &[&str]) -> Vec<(&'static str, Value)> {
+        let all = [
+            ("slot", json!(self.slot)),
+            ("slot_min", json!(self.slot_min)),
+            ("epoch", json!(self.epoch)),
+            ("tps", json!(self.tps)),
+            ("tps_min", json!(self.tps_min)),
+            ("tps_max", json!(self.tps_max)),
+            ("tps_p95", json!(self.tps_p95)),
+            ("latency_ms", json!(self.latency_ms)),
+            ("latency_min_ms", json!(self.latency_min_ms)),
+            ("latency_max_ms", json!(self.latency_max_ms)),
+            ("latency_p95_ms", json!(self.latency_p95_ms)),
+            ("status", json!(self.status)),
+            ("status_changes", json!(self.status_changes)),
+        ];
+        let mut columns = vec![("timestamp", json!(self.timestamp.to_rfc3339()))];
+        columns.extend(all.into_iter().filter(|(name, _)| fields.contains(name)));
+        columns
+    }
+}
+
+fn bad_request(msg: impl Into<String>) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": msg.into() }))).into_response()
+}
+
+/// Points at `resolution` (raw samples for `None`) in `[from, to)`, skipping those at
+/// `from` with an id up to `after_id`. Reads one extra row to tell whether there is a
+/// next page, returned as the `(timestamp, id)` of the last point kept.
+async fn fetch_points(
+    db: &Pool<Sqlite>,
+    resolution: Option<Resolution>,
+    from: DateTime<Utc>,
+    after_id: Option<i64>,
+    to: DateTime<Utc>,
+    limit: usize,
+) -> Result<(Vec<HistoryPoint>, Option<(DateTime<Utc>, i64)>), sqlx::Error> {
+    // Timestamps only have second precision, so a cursor on time alone would skip the
+    // rest of a second that a page boundary falls in.
+    let query = match resolution {
+        None => sqlx::query_as(
+            "SELECT id, sampled_at AS timestamp, slot, NULL AS slot_min, epoch,
+                    CAST(tps AS REAL) AS tps, NULL AS tps_min, NULL AS tps_max, NULL AS tps_p95,
+                    CAST(latency_ms AS REAL) AS latency_ms, NULL AS latency_min_ms,
+                    NULL AS latency_max_ms, NULL AS latency_p95_ms, status, NULL AS status_changes
+             FROM metric_samples
+             WHERE (sampled_at > datetime(?) OR (sampled_at = datetime(?) AND id > ?))
+               AND sampled_at < datetime(?)
+             ORDER BY sampled_at, id LIMIT ?",
+        ),
+        Some(r) => sqlx::query_as(
+            "SELECT rowid AS id, bucket_start AS timestamp, slot_max AS slot, slot_min, epoch,
+                    tps_avg AS tps, tps_min, tps_max, tps_p95,
+                    latency_avg AS latency_ms, latency_min AS latency_min_ms,
+                    latency_max AS latency_max_ms, latency_p95 AS latency_p95_ms, status, status_changes
+             FROM metric_rollups
+             WHERE resolution = ?
+               AND (bucket_start > datetime(?) OR (bucket_start = datetime(?) AND rowid > ?))
+               AND bucket_start < datetime(?)
+             ORDER BY bucket_start, rowid LIMIT ?",
+        )
+        .bind(r.as_str()),
+    };
+    let mut points: Vec<HistoryPoint> = query
+        .bind(from)
+        .bind(from)
+        .bind(after_id.unwrap_or(i64::MIN))
+        .bind(to)
+        .bind(limit as i64 + 1)
+        .fetch_all(db)
+        .await?;
+
+    let next = if points.len() > limit {
+        points.truncate(limit);
+        points.last().map(|p| (p.timestamp, p.id))
+    } else {
+        None
+    };
+    Ok((points, next))
+}
+
+/// Time-bucketed engine history for key holders. Pages forward: when more points exist
+/// than were returned, `next_from` and `next_after_id` are the `from` and `after_id` of
+/// the next page.
+pub async fn metrics_history(
+    State(state): State<Arc<AppState>>,
+    Extension(key): Extension<AuthedKey>,
+    headers: HeaderMap,
+    Query(q): Query<HistoryQuery>,
+) -> Response {
+    let to = q.to.unwrap_or_else(Utc::now);
+    let from = q.from.unwrap_or(to - ChronoDuration::hours(1));
+    if from >= to {
+        return bad_request("`from` must be before `to`");
+    }
+
+    let resolution = match q.resolution.as_deref().unwrap_or("1m") {
+        "raw" => None,
+        other => match Resolution::parse(other) {
+            Some(r) => Some(r),
+            None => return bad_request(format!("unknown resolution '{}', use raw, 1m, 1h or 1d", other)),
+        },
+    };
+
+    let mut fields: Vec<&str> = Vec::new();
+    match q.fields.as_deref() {
+        None => fields.extend(FIELD_GROUPS.iter().flat_map(|(_, cols)| cols.iter().copied())),
+        Some(list) => {
+            for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                match FIELD_GROUPS.iter().find(|(group, _)| *group == name) {
+                    Some((_, cols)) => fields.extend(cols.iter().copied()),
+                    None => return bad_request(format!("unknown field '{}'", name)),
+                }
+            }
+        }
+    }
+
+    let tier: String = sqlx::query_scalar("SELECT tier FROM users WHERE id = ?")
+        .bind(key.user_id)
+        .fetch_optional(&state.db)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "free".to_string());
+    let max_points = max_points_for_tier(&tier);
+    let limit = q.limit.unwrap_or(max_points).clamp(1, max_points);
+
+    let (points, next) = match fetch_points(&state.db, resolution, from, q.after_id, to, limit).await {
+        Ok(page) => page,
+        Err(e) => {
+            tracing::error!(error = %e, "metrics_history query failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Database error" }))).into_response();
+        }
+    };
+
+    let wants_csv = q.format.as_deref() == Some("csv")
+        || headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()).is_some_and(|v| v.contains("text/csv"));
+    if wants_csv {
+        return csv_response(&points, &fields, next);
+    }
+
+    let data: Vec<Value> = points.iter()
+        .map(|p| Value::Object(p.columns(&fields).into_iter().map(|(k, v)| (k.to_string(), v)).collect::<Map<_, _>>()))
+        .collect();
+
+    let mut body = routes::envelope(Value::Array(data));
+    body["resolution"] = json!(resolution.map_or("raw", |r| r.as_str()));
+    body["from"] = json!(from.to_rfc3339());
+    body["to"] = json!(to.to_rfc3339());
+    body["limit"] = json!(limit);
+    body["next_from"] = json!(next.map(|(t, _)| t.to_rfc3339()));
+    body["next_after_id"] = json!(next.map(|(_, id)| id));
+    Json(body).into_response()
+}
+
+fn csv_response(points: &[HistoryPoint], fields: &[&str], next: Option<(DateTime<Utc>, i64)>) -> Response {
+    let mut out = String::new();
+    let header_row = std::iter::once("timestamp").chain(
+        FIELD_GROUPS.iter().flat_map(|(_, cols)| cols.iter().copied()).filter(|c| fields.contains(c)),
+    );
+    out.push_str(&header_row.collect::<Vec<_>>().join(","));
+    out.push('\n');
+
+    for point in points {
+        let row: Vec<String> = point.columns(fields).into_iter()
+            .map(|(_, v)| match v {
+                Value::Null => String::new(),
+                Value::String(s) if s.contains([',', '"', '\n']) => format!("\"{}\"", s.replace('"', "\"\"")),
+                Value::String(s) => s,
+                other => other.to_string(),
+            })
+            .collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+
+    let mut response = ([(header::CONTENT_TYPE, "text/csv; charset=utf-8")], out).into_response();
+    if let Some((from, after_id)) = next {
+        let headers = response.headers_mut();
+        if let Ok(from) = from.to_rfc3339().parse() {
+            headers.insert("x-next-from", from);
+        }
+        headers.insert("x-next-after-id", after_id.into());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory_pool;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        // 2026-01-01T00:00:00Z, on a day boundary so every resolution's buckets line up.
+        Utc.timestamp_opt(1_767_225_600 + secs, 0).unwrap()
+    }
+
+    async fn sample(db: &Pool<Sqlite>, t: DateTime<Utc>, latency_ms: i64, tps: Option<i64>, status: &str) {
+        sqlx::query(
+            "INSERT INTO metric_samples (sampled_at, slot, epoch, tps, latency_ms, status)
+             VALUES (datetime(?), ?, 600, ?, ?, ?)",
+        )
+        .bind(t)
+        .bind(1_000 + t.timestamp() % 1_000)
+        .bind(tps)
+        .bind(latency_ms)
+        .bind(status)
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    async fn count(db: &Pool<Sqlite>, sql: &str) -> i64 {
+        sqlx::query_scalar(sql).fetch_one(db).await.unwrap()
+    }
+
+    #[test]
+    fn stats_use_nearest_rank() {
+        let mut values: Vec<f64> = (1..=20).rev().map(f64::from).collect();
+        let stats = Stats::of(&mut values).unwrap();
+        assert_eq!(stats, Stats { min: 1.0, max: 20.0, avg: 10.5, p95: 19.0 });
+
+        assert_eq!(Stats::of(&mut [7.0]).unwrap().p95, 7.0);
+        assert!(Stats::of(&mut []).is_none());
+    }
+
+    #[test]
+    fn point_limits_by_tier() {
+        assert_eq!(max_points_for_tier("free"), 500);
+        assert_eq!(max_points_for_tier("Pro"), 5_000);
+        assert_eq!(max_points_for_tier("enterprise"), 50_000);
+        assert_eq!(max_points_for_tier("something-else"), 500);
+    }
+
+    #[tokio::test]
+    async fn roll_up_writes_closed_buckets_once() {
+        let db = memory_pool().await;
+        sample(&db, at(0), 10, Some(2_000), "OPERATIONAL").await;
+        sample(&db, at(20), 40, None, "DEGRADED").await;
+        sample(&db, at(40), 30, Some(3_000), "OPERATIONAL").await;
+        sample(&db, at(70), 50, Some(2_500), "OPERATIONAL").await;
+
+        // The second minute is still open at 1m30s.
+        assert_eq!(roll_up(&db, Resolution::Minute, at(90)).await.unwrap(), 1);
+        assert_eq!(roll_up(&db, Resolution::Minute, at(90)).await.unwrap(), 0);
+        assert_eq!(roll_up(&db, Resolution::Hour, at(90)).await.unwrap(), 0);
+
+        let row: (i64, f64, f64, f64, Option<f64>, String, i64) = sqlx::query_as(
+            "SELECT samples, latency_min, latency_max, latency_p95, tps_avg, status, status_changes
+             FROM metric_rollups WHERE resolution = '1m' AND bucket_start = datetime(?)",
+        )
+        .bind(at(0))
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(row, (3, 10.0, 40.0, 40.0, Some(2_500.0), "OPERATIONAL".to_string(), 2));
+
+        assert_eq!(roll_up(&db, Resolution::Minute, at(130)).await.unwrap(), 1);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM metric_rollups WHERE resolution = '1m'").await, 2);
+    }
+
+    #[tokio::test]
+    async fn status_changes_on_bucket_boundaries_count() {
+        let db = memory_pool().await;
+        sample(&db, at(0), 10, None, "OPERATIONAL").await;
+        sample(&db, at(30), 10, None, "OPERATIONAL").await;
+        sample(&db, at(60), 10, None, "DEGRADED").await;
+        sample(&db, at(90), 10, None, "DEGRADED").await;
+        sample(&db, at(3_600), 10, None, "OPERATIONAL").await;
+
+        // The first minute alone, then the rest, so both the carried and the queried
+        // previous sample are exercised.
+        assert_eq!(roll_up(&db, Resolution::Minute, at(60)).await.unwrap(), 1);
+        assert_eq!(roll_up(&db, Resolution::Minute, at(3_660)).await.unwrap(), 2);
+        assert_eq!(roll_up(&db, Resolution::Hour, at(7_200)).await.unwrap(), 2);
+
+        let changes: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT resolution, status_changes FROM metric_rollups ORDER BY resolution, bucket_start",
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        let expected = [("1h", 1), ("1h", 1), ("1m", 0), ("1m", 1), ("1m", 1)];
+        assert_eq!(changes, expected.map(|(r, n)| (r.to_string(), n)));
+    }
+
+    #[tokio::test]
+    async fn prune_honours_each_retention() {
+        let db = memory_pool().await;
+        let config = HistoryConfig::default();
+        let now = at(30 * 86_400);
+        sample(&db, now - ChronoDuration::days(4), 10, None, "OPERATIONAL").await;
+        sample(&db, now - ChronoDuration::hours(1), 10, None, "OPERATIONAL").await;
+        for (resolution, age) in [("1m", 8), ("1m", 1), ("1h", 91), ("1h", 8)] {
+            sqlx::query(
+                "INSERT INTO metric_rollups (resolution, bucket_start, samples, slot_min, slot_max, epoch,
+                    latency_min, latency_max, latency_avg, latency_p95, status)
+                 VALUES (?, datetime(?), 1, 1, 1, 1, 1, 1, 1, 1, 'OPERATIONAL')",
+            )
+            .bind(resolution)
+            .bind(now - ChronoDuration::days(age))
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+
+        prune(&db, &config, now).await.unwrap();
+
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM metric_samples").await, 1);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM metric_rollups WHERE resolution = '1m'").await, 1);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM metric_rollups WHERE resolution = '1h'").await, 1);
+    }
+
+    #[tokio::test]
+    async fn paging_does_not_skip_rows_sharing_a_second() {
+        let db = memory_pool().await;
+        for _ in 0..5 {
+            sample(&db, at(10), 10, None, "OPERATIONAL").await;
+        }
+        sample(&db, at(11), 10, None, "OPERATIONAL").await;
+
+        let mut seen = Vec::new();
+        let (mut from, mut after_id) = (at(0), None);
+        loop {
+            let (points, next) = fetch_points(&db, None, from, after_id, at(60), 2).await.unwrap();
+            seen.extend(points.iter().map(|p| p.id));
+            match next {
+                Some((t, id)) => (from, after_id) = (t, Some(id)),
+                None => break,
+            }
+        }
+        assert_eq!(seen, (1..=6).collect::<Vec<i64>>());
+    }
+
+    #[tokio::test]
+    async fn csv_quotes_text_and_carries_the_cursor() {
+        let point = HistoryPoint {
+            id: 42,
+            timestamp: at(0),
+            slot: 1_000,
+            slot_min: None,
+            epoch: 600,
+            tps: None,
+            tps_min: None,
+            tps_max: None,
+            tps_p95: None,
+            latency_ms: 12.0,
+            latency_min_ms: None,
+            latency_max_ms: None,
+            latency_p95_ms: None,
+            status: "DEGRADED, \"slow\"".to_string(),
+            status_changes: Some(1),
+        };
+
+        let response = csv_response(&[point], &["slot", "tps", "status"], Some((at(0), 42)));
+        assert_eq!(response.headers()["x-next-from"], "2026-01-01T00:00:00+00:00");
+        assert_eq!(response.headers()["x-next-after-id"], "42");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            "timestamp,slot,tps,status\n2026-01-01T00:00:00+00:00,1000,,\"DEGRADED, \"\"slow\"\"\"\n",
+        );
+    }
+}
@@ -0,0 +1,476 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::{Pool, Sqlite};
+use std::sync::Arc;
+use crate::auth::{random_bytes, Session};
+use crate::config;
+use crate::models::{ApiKey, AppState};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const KEY_PREFIX: &str = "sk_live";
+
+/// Secret used to hash API keys at rest. Unlike the session secret this cannot be
+/// ephemeral: rotating it invalidates every issued key.
+pub fn hashing_secret() -> &'static [u8] {
+    config::get().api_key_secret.as_deref()
+        .expect("api_key_secret must be set (ARKHEION_API_KEY_SECRET, at least 32 characters)")
+        .as_bytes()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    MetricsRead,
+    StreamRead,
+    BillingWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::MetricsRead, Scope::StreamRead, Scope::BillingWrite];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::MetricsRead => "metrics:read",
+            Scope::StreamRead => "stream:read",
+            Scope::BillingWrite => "billing:write",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scope| scope.as_str() == s)
+    }
+}
+
+/// Scopes handed to keys that don't ask for any, and to legacy single keys in `users.api_key`.
+pub const DEFAULT_SCOPES: &str = "metrics:read stream:read";
+
+/// A freshly minted key. `key` is returned to the user exactly once; only `stored` is persisted.
+pub struct MintedKey {
+    pub key: String,
+    pub key_id: String,
+    pub stored: String,
+}
+
+/// Keys look like `sk_live_<key_id>_<secret>`. The key id is public and used for lookup,
+/// the secret part never leaves the response that created it.
+pub fn mint() -> MintedKey {
+    let key_id = bs58::encode(random_bytes(6)).into_string();
+    let secret = bs58::encode(random_bytes(24)).into_string();
+    let key = format!("{}_{}_{}", KEY_PREFIX, key_id, secret);
+    let stored = format!("{}:{}", key_id, hash(&key));
+    MintedKey { key, key_id, stored }
+}
+
+/// Extracts the key id; bs58 never produces `_`, so the split is unambiguous. Ids that
+/// are not bs58 are rejected here, before they reach a query or a rate-limit bucket.
+pub fn key_id(key: &str) -> Option<&str> {
+    let rest = key.strip_prefix(KEY_PREFIX)?.strip_prefix('_')?;
+    let (id, secret) = rest.split_once('_')?;
+    let well_formed = !id.is_empty() && !secret.is_empty() && bs58::decode(id).into_vec().is_ok();
+    well_formed.then_some(id)
+}
+
+fn mac(key: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(hashing_secret()).expect("HMAC accepts any key length");
+    mac.update(key.as_bytes());
+    mac
+}
+
+pub fn hash(key: &str) -> String {
+    mac(key).finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Constant-time check of a presented key against a stored `<key_id>:<hex hmac>` value.
+pub fn verify(key: &str, stored: &str) -> bool {
+    let Some((_, hex)) = stored.split_once(':') else { return false };
+    let Some(expected) = decode_hex(hex) else { return false };
+    mac(key).verify_slice(&expected).is_ok()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// The key that authenticated a request. `api_key_id` is `None` for legacy `users.api_key` keys.
+#[derive(Debug, Clone)]
+pub struct AuthedKey {
+    pub user_id: i64,
+    pub api_key_id: Option<i64>,
+}
+
+#[derive(Debug)]
+pub enum KeyError {
+    Missing,
+    Invalid,
+    Revoked,
+    Expired,
+    MissingScope(Scope),
+    Db(sqlx::Error),
+}
+
+impl IntoResponse for KeyError {
+    fn into_response(self) -> Response {
+        let (status, msg) = match self {
+            KeyError::Missing => (StatusCode::UNAUTHORIZED, "Missing API Key".to_string()),
+            KeyError::Invalid => (StatusCode::UNAUTHORIZED, "Invalid API Key".to_string()),
+            KeyError::Revoked => (StatusCode::UNAUTHORIZED, "API Key revoked".to_string()),
+            KeyError::Expired => (StatusCode::UNAUTHORIZED, "API Key expired".to_string()),
+            KeyError::MissingScope(scope) => (StatusCode::FORBIDDEN, format!("API Key lacks scope {}", scope.as_str())),
+            KeyError::Db(e) => {
+                tracing::error!(error = %e, "api key lookup failed");
+                (StatusCode::INTERNAL_SERVER_ERROR, "Could not check API Key".to_string())
+            }
+        };
+        (status, Json(json!({ "error": msg }))).into_response()
+    }
+}
+
+impl From<sqlx::Error> for KeyError {
+    fn from(e: sqlx::Error) -> Self {
+        KeyError::Db(e)
+    }
+}
+
+/// Reads the key from `Authorization: Bearer <key>`, falling back to `?key=`.
+pub fn presented_key(headers: &HeaderMap, query_key: Option<String>) -> Option<String> {
+    headers.get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim().to_string())
+        .or(query_key)
+}
+
+/// Resolves a presented key and checks it carries `scope`. Bumps `last_used_at` on success.
+pub async fn authorize(db: &Pool<Sqlite>, key: Option<&str>, scope: Scope) -> Result<AuthedKey, KeyError> {
+    let key = key.ok_or(KeyError::Missing)?;
+    let id = key_id(key).ok_or(KeyError::Invalid)?;
+
+    let row: Option<ApiKey> = sqlx::query_as("SELECT * FROM api_keys WHERE key_id = ?")
+        .bind(id)
+        .fetch_optional(db)
+        .await?;
+
+    let Some(row) = row else {
+        return authorize_legacy(db, key, id, scope).await;
+    };
+
+    if !verify(key, &row.key_hash) {
+        return Err(KeyError::Invalid);
+    }
+    if row.revoked {
+        return Err(KeyError::Revoked);
+    }
+    if row.expires_at.is_some_and(|t| t <= Utc::now()) {
+        return Err(KeyError::Expired);
+    }
+    if !row.scopes.split_whitespace().any(|s| s == scope.as_str()) {
+        return Err(KeyError::MissingScope(scope));
+    }
+
+    sqlx::query("UPDATE api_keys SET last_used_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(row.id)
+        .execute(db)
+        .await?;
+
+    Ok(AuthedKey { user_id: row.user_id, api_key_id: Some(row.id) })
+}
+
+/// Re-validates an already authorized key for long-lived connections, without the plaintext.
+pub async fn recheck(db: &Pool<Sqlite>, key: &AuthedKey) -> Result<(), KeyError> {
+    let Some(id) = key.api_key_id else {
+        // Legacy keys can only disappear with their user.
+        let exists: Option<i64> = sqlx::query_scalar("SELECT id FROM users WHERE id = ?")
+            .bind(key.user_id)
+            .fetch_optional(db)
+            .await?;
+        return exists.map(|_| ()).ok_or(KeyError::Invalid);
+    };
+
+    let row: Option<(bool, Option<chrono::DateTime<Utc>>)> =
+        sqlx::query_as("SELECT revoked, expires_at FROM api_keys WHERE id = ?")
+            .bind(id)
+            .fetch_optional(db)
+            .await?;
+
+    match row {
+        None => Err(KeyError::Invalid),
+        Some((true, _)) => Err(KeyError::Revoked),
+        Some((_, Some(expires_at))) if expires_at <= Utc::now() => Err(KeyError::Expired),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Keys issued before `api_keys` existed live in `users.api_key` and get [`DEFAULT_SCOPES`].
+async fn authorize_legacy(db: &Pool<Sqlite>, key: &str, id: &str, scope: Scope) -> Result<AuthedKey, KeyError> {
+    // Exact prefix match: `id` comes from the caller and must not act as a pattern.
+    let candidates: Vec<(i64, String)> =
+        sqlx::query_as("SELECT id, api_key FROM users WHERE substr(api_key, 1, length(?) + 1) = ? || ':'")
+            .bind(id)
+            .bind(id)
+            .fetch_all(db)
+            .await?;
+
+    let user_id = candidates.into_iter()
+        .find(|(_, stored)| verify(key, stored))
+        .map(|(user_id, _)| user_id)
+        .ok_or(KeyError::Invalid)?;
+
+    if !DEFAULT_SCOPES.split_whitespace().any(|s| s == scope.as_str()) {
+        return Err(KeyError::MissingScope(scope));
+    }
+    Ok(AuthedKey { user_id, api_key_id: None })
+}
+
+/// Inserts a new key row and returns the plaintext key alongside it. Takes the pool or a
+/// transaction.
+pub async fn create_key<'e>(
+    db: impl sqlx::Executor<'e, Database = Sqlite>,
+    user_id: i64,
+    label: &str,
+    scopes: &str,
+    expires_at: Option<chrono::DateTime<Utc>>,
+) -> Result<(String, ApiKey), sqlx::Error> {
+    let minted = mint();
+
+    let row = sqlx::query_as(
+        "INSERT INTO api_keys (user_id, key_id, key_hash, label, scopes, expires_at)
+         VALUES (?, ?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(user_id)
+    .bind(&minted.key_id)
+    .bind(&minted.stored)
+    .bind(label)
+    .bind(scopes)
+    .bind(expires_at)
+    .fetch_one(db)
+    .await?;
+
+    Ok((minted.key, row))
+}
+
+fn error(status: StatusCode, msg: &str) -> Response {
+    (status, Json(json!({ "error": msg }))).into_response()
+}
+
+fn db_error(ctx: &str, e: sqlx::Error) -> Response {
+    tracing::error!(error = %e, "{} failed", ctx);
+    error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+}
+
+pub async fn list_keys(State(state): State<Arc<AppState>>, session: Session) -> Response {
+    let keys: Result<Vec<ApiKey>, _> = sqlx::query_as("SELECT * FROM api_keys WHERE user_id = ? ORDER BY created_at DESC")
+        .bind(session.user_id)
+        .fetch_all(&state.db)
+        .await;
+
+    match keys {
+        Ok(keys) => Json(json!({ "keys": keys })).into_response(),
+        Err(e) => db_error("list_keys", e),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateKeyRequest {
+    pub label: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<i64>,
+}
+
+pub async fn create_key_handler(
+    State(state): State<Arc<AppState>>,
+    session: Session,
+    Json(req): Json<CreateKeyRequest>,
+) -> Response {
+    let label = req.label.trim();
+    if label.is_empty() || label.len() > 64 {
+        return error(StatusCode::BAD_REQUEST, "Label must be 1-64 characters");
+    }
+
+    let scopes = if req.scopes.is_empty() {
+        DEFAULT_SCOPES.to_string()
+    } else {
+        if let Some(bad) = req.scopes.iter().find(|s| Scope::parse(s).is_none()) {
+            return error(StatusCode::BAD_REQUEST, &format!("Unknown scope {}", bad));
+        }
+        req.scopes.join(" ")
+    };
+
+    let expires_at = match req.expires_in_days {
+        Some(days) if days <= 0 => return error(StatusCode::BAD_REQUEST, "expires_in_days must be positive"),
+        Some(days) => Some(Utc::now() + Duration::days(days)),
+        None => None,
+    };
+
+    match create_key(&state.db, session.user_id, label, &scopes, expires_at).await {
+        Ok((key, row)) => (StatusCode::CREATED, Json(json!({ "key": key, "api_key": row }))).into_response(),
+        Err(e) => db_error("create_key", e),
+    }
+}
+
+pub async fn revoke_key(State(state): State<Arc<AppState>>, session: Session, Path(id): Path<i64>) -> Response {
+    let res = sqlx::query("UPDATE api_keys SET revoked = 1 WHERE id = ? AND user_id = ?")
+        .bind(id)
+        .bind(session.user_id)
+        .execute(&state.db)
+        .await;
+
+    match res {
+        Ok(r) if r.rows_affected() == 0 => error(StatusCode::NOT_FOUND, "Key not found"),
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => db_error("revoke_key", e),
+    }
+}
+
+/// Revokes a key and issues a replacement with the same label, scopes and expiry.
+pub async fn rotate_key(State(state): State<Arc<AppState>>, session: Session, Path(id): Path<i64>) -> Response {
+    let old: Option<ApiKey> = match sqlx::query_as("SELECT * FROM api_keys WHERE id = ? AND user_id = ? AND revoked = 0")
+        .bind(id)
+        .bind(session.user_id)
+        .fetch_optional(&state.db)
+        .await
+    {
+        Ok(row) => row,
+        Err(e) => return db_error("rotate_key", e),
+    };
+
+    let Some(old) = old else {
+        return error(StatusCode::NOT_FOUND, "Key not found");
+    };
+
+    // Revoke and replace together: a failed insert must not leave the user without a key.
+    let mut tx = match state.db.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return db_error("rotate_key", e),
+    };
+
+    let revoked = sqlx::query("UPDATE api_keys SET revoked = 1 WHERE id = ? AND revoked = 0")
+        .bind(old.id)
+        .execute(&mut *tx)
+        .await;
+    match revoked {
+        Ok(r) if r.rows_affected() == 0 => return error(StatusCode::CONFLICT, "Key was revoked concurrently"),
+        Ok(_) => {}
+        Err(e) => return db_error("rotate_key", e),
+    }
+
+    let (key, row) = match create_key(&mut *tx, session.user_id, &old.label, &old.scopes, old.expires_at).await {
+        Ok(created) => created,
+        Err(e) => return db_error("rotate_key", e),
+    };
+    if let Err(e) = tx.commit().await {
+        return db_error("rotate_key", e);
+    }
+
+    (StatusCode::CREATED, Json(json!({ "key": key, "api_key": row, "revoked": old.id }))).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory_pool;
+
+    async fn user(db: &Pool<Sqlite>, email: &str, api_key: &str) -> i64 {
+        sqlx::query_scalar("INSERT INTO users (email, api_key) VALUES (?, ?) RETURNING id")
+            .bind(email)
+            .bind(api_key)
+            .fetch_one(db)
+            .await
+            .unwrap()
+    }
+
+    /// The same key with the last character of its secret changed.
+    fn tampered(key: &str) -> String {
+        let mut key = key.to_string();
+        let last = key.pop().unwrap();
+        key.push(if last == '2' { '3' } else { '2' });
+        key
+    }
+
+    #[test]
+    fn minted_keys_round_trip() {
+        config::init_for_tests();
+        let minted = mint();
+        assert!(minted.key.starts_with("sk_live_"));
+        assert_eq!(key_id(&minted.key), Some(minted.key_id.as_str()));
+        assert!(minted.stored.starts_with(&format!("{}:", minted.key_id)));
+        assert!(verify(&minted.key, &minted.stored));
+
+        assert!(!verify(&tampered(&minted.key), &minted.stored));
+        assert!(!verify(&minted.key, &mint().stored));
+        assert!(!verify(&minted.key, "no-separator"));
+        assert!(!verify(&minted.key, "id:not-hex"));
+    }
+
+    #[test]
+    fn malformed_ids_are_rejected() {
+        assert_eq!(key_id("sk_live_3yQ_secret"), Some("3yQ"));
+        for key in ["sk_live_0OIl_secret", "sk_live_a%_secret", "sk_live__secret", "sk_live_3yQ_", "sk_live_3yQ", "sk_test_3yQ_secret", "3yQ_secret"] {
+            assert_eq!(key_id(key), None, "{}", key);
+        }
+    }
+
+    #[tokio::test]
+    async fn authorize_checks_hash_revocation_expiry_and_scope() {
+        config::init_for_tests();
+        let db = memory_pool().await;
+        let user_id = user(&db, "a@example.com", &mint().stored).await;
+
+        let (key, row) = create_key(&db, user_id, "bot", "metrics:read", None).await.unwrap();
+        let authed = authorize(&db, Some(&key), Scope::MetricsRead).await.unwrap();
+        assert_eq!((authed.user_id, authed.api_key_id), (user_id, Some(row.id)));
+
+        assert!(matches!(authorize(&db, None, Scope::MetricsRead).await, Err(KeyError::Missing)));
+        assert!(matches!(authorize(&db, Some(&tampered(&key)), Scope::MetricsRead).await, Err(KeyError::Invalid)));
+        assert!(matches!(
+            authorize(&db, Some(&key), Scope::StreamRead).await,
+            Err(KeyError::MissingScope(Scope::StreamRead))
+        ));
+
+        sqlx::query("UPDATE api_keys SET revoked = 1 WHERE id = ?").bind(row.id).execute(&db).await.unwrap();
+        assert!(matches!(authorize(&db, Some(&key), Scope::MetricsRead).await, Err(KeyError::Revoked)));
+
+        let expired = Some(Utc::now() - Duration::minutes(1));
+        let (key, _) = create_key(&db, user_id, "old", DEFAULT_SCOPES, expired).await.unwrap();
+        assert!(matches!(authorize(&db, Some(&key), Scope::MetricsRead).await, Err(KeyError::Expired)));
+    }
+
+    #[tokio::test]
+    async fn legacy_keys_match_their_exact_id_only() {
+        config::init_for_tests();
+        let db = memory_pool().await;
+        let legacy = mint();
+        let user_id = user(&db, "legacy@example.com", &legacy.stored).await;
+
+        let authed = authorize(&db, Some(&legacy.key), Scope::StreamRead).await.unwrap();
+        assert_eq!((authed.user_id, authed.api_key_id), (user_id, None));
+        assert!(matches!(
+            authorize(&db, Some(&legacy.key), Scope::BillingWrite).await,
+            Err(KeyError::MissingScope(Scope::BillingWrite))
+        ));
+
+        // A presented id that is a prefix of a stored one, or would be a LIKE pattern, finds nothing.
+        let secret = legacy.key.rsplit_once('_').unwrap().1;
+        let prefix = &legacy.key_id[..legacy.key_id.len() - 1];
+        for key in [format!("sk_live_{}_{}", prefix, secret), format!("sk_live_%_{}", secret)] {
+            assert!(matches!(authorize(&db, Some(&key), Scope::MetricsRead).await, Err(KeyError::Invalid)), "{}", key);
+        }
+
+        // Another user whose stored id merely starts with this one does not collide.
+        let longer = format!("{}z:{}", legacy.key_id, legacy.stored.split_once(':').unwrap().1);
+        user(&db, "other@example.com", &longer).await;
+        let authed = authorize(&db, Some(&legacy.key), Scope::MetricsRead).await.unwrap();
+        assert_eq!(authed.user_id, user_id);
+    }
+}
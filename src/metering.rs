@@ -0,0 +1,292 @@
+use axum::{
+    extract::{Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{Pool, Sqlite};
+use std::sync::Arc;
+use crate::auth::Session;
+use crate::keys::{self, AuthedKey, Scope};
+use crate::models::AppState;
+
+/// Scope and credit cost of every metered route, matched by path prefix (first match wins).
+/// Unlisted `/api/v1/*` paths cost [`DEFAULT_COST`] and need `metrics:read`.
+const PRICE_TABLE: &[(&str, Scope, i64)] = &[
+    ("/api/v1/stream", Scope::StreamRead, 1),
+    ("/api/v1/ws", Scope::StreamRead, 1),
+    ("/api/v1/metrics", Scope::MetricsRead, 1),
+];
+const DEFAULT_COST: i64 = 1;
+
+pub fn price_for(path: &str) -> (Scope, i64) {
+    PRICE_TABLE.iter()
+        .find(|(prefix, _, _)| path.starts_with(prefix))
+        .map(|(_, scope, cost)| (*scope, *cost))
+        .unwrap_or((Scope::MetricsRead, DEFAULT_COST))
+}
+
+#[derive(Deserialize)]
+struct KeyQuery {
+    key: Option<String>,
+}
+
+/// Authenticates the API key, charges the route's cost up front and refunds it if the
+/// handler fails, so concurrent calls can never take `credits` below zero.
+/// The resolved [`AuthedKey`] is handed to handlers as a request extension.
+pub async fn meter(State(state): State<Arc<AppState>>, mut req: Request, next: Next) -> Response {
+    let path = req.uri().path().to_string();
+    let (scope, cost) = price_for(&path);
+
+    let query_key = Query::<KeyQuery>::try_from_uri(req.uri()).ok().and_then(|q| q.0.key);
+    let presented = keys::presented_key(req.headers(), query_key);
+    let authed = match keys::authorize(&state.db, presented.as_deref(), scope).await {
+        Ok(authed) => authed,
+        Err(e) => return e.into_response(),
+    };
+
+    match debit(&state.db, authed.user_id, cost).await {
+        Ok(true) => {}
+        Ok(false) => return payment_required(&state.db, authed.user_id, cost).await,
+        Err(e) => {
+            tracing::error!(error = %e, "meter failed");
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Metering unavailable" }))).into_response();
+        }
+    }
+
+    req.extensions_mut().insert(authed.clone());
+    let response = next.run(req).await;
+
+    let status = response.status();
+    // 101: a WebSocket upgrade succeeded; the socket bills itself from here on.
+    let charged = if status.is_success() || status == StatusCode::SWITCHING_PROTOCOLS { cost } else { 0 };
+    if charged == 0 {
+        // Undo both halves of `debit`: failed calls are neither charged nor counted.
+        let refund = sqlx::query("UPDATE users SET credits = credits + ?, requests = requests - 1 WHERE id = ?")
+            .bind(cost)
+            .bind(authed.user_id)
+            .execute(&state.db)
+            .await;
+        if let Err(e) = refund {
+            tracing::error!(error = %e, user_id = authed.user_id, "refund failed");
+        }
+    }
+
+    if let Err(e) = record_usage(&state.db, &authed, &path, charged, status.as_u16()).await {
+        tracing::error!(error = %e, "usage ledger insert failed");
+    }
+
+    response
+}
+
+/// Takes `cost` credits if the balance covers it. `false` means the user is out of credits.
+pub async fn debit(db: &Pool<Sqlite>, user_id: i64, cost: i64) -> Result<bool, sqlx::Error> {
+    let r = sqlx::query(
+        "UPDATE users SET credits = credits - ?, requests = requests + 1, last_active = CURRENT_TIMESTAMP
+         WHERE id = ? AND credits >= ?",
+    )
+    .bind(cost)
+    .bind(user_id)
+    .bind(cost)
+    .execute(db)
+    .await?;
+    Ok(r.rows_affected() == 1)
+}
+
+async fn payment_required(db: &Pool<Sqlite>, user_id: i64, cost: i64) -> Response {
+    let remaining: i64 = sqlx::query_scalar("SELECT credits FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_one(db)
+        .await
+        .unwrap_or(0);
+
+    (
+        StatusCode::PAYMENT_REQUIRED,
+        Json(json!({
+            "error": "Insufficient credits",
+            "code": "CREDITS_EXHAUSTED",
+            "credits_required": cost,
+            "credits_remaining": remaining,
+            "top_up": "/api/v1/billing/payments"
+        })),
+    )
+        .into_response()
+}
+
+pub async fn record_usage(db: &Pool<Sqlite>, key: &AuthedKey, endpoint: &str, cost: i64, status: u16) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO usage_ledger (user_id, api_key_id, endpoint, cost, status_code) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(key.user_id)
+    .bind(key.api_key_id)
+    .bind(endpoint)
+    .bind(cost)
+    .bind(status)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Invoice view of the ledger: calls and credits per key and endpoint in `[from, to)`.
+pub async fn usage_report(
+    State(state): State<Arc<AppState>>,
+    session: Session,
+    Query(q): Query<UsageQuery>,
+) -> Response {
+    let to = q.to.unwrap_or_else(Utc::now);
+    let from = q.from.unwrap_or(to - chrono::Duration::days(30));
+
+    let rows: Result<Vec<(Option<i64>, String, i64, i64)>, _> = sqlx::query_as(
+        "SELECT api_key_id, endpoint, COUNT(*), COALESCE(SUM(cost), 0) FROM usage_ledger
+         WHERE user_id = ? AND created_at >= datetime(?) AND created_at < datetime(?)
+         GROUP BY api_key_id, endpoint ORDER BY api_key_id, endpoint",
+    )
+    .bind(session.user_id)
+    .bind(from)
+    .bind(to)
+    .fetch_all(&state.db)
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let total: i64 = rows.iter().map(|r| r.3).sum();
+            let lines: Vec<_> = rows.into_iter()
+                .map(|(api_key_id, endpoint, calls, credits)| json!({
+                    "api_key_id": api_key_id,
+                    "endpoint": endpoint,
+                    "calls": calls,
+                    "credits": credits
+                }))
+                .collect();
+            Json(json!({
+                "from": from.to_rfc3339(),
+                "to": to.to_rfc3339(),
+                "total_credits": total,
+                "lines": lines
+            }))
+            .into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "usage_report query failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": "Database error" }))).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::db::memory_pool;
+    use crate::rpc_source::mock::ScriptedRpc;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+
+    struct Fixture {
+        state: Arc<AppState>,
+        user_id: i64,
+        key: String,
+    }
+
+    impl Fixture {
+        async fn new(credits: i64) -> Self {
+            config::init_for_tests();
+            let db = memory_pool().await;
+            let user_id: i64 = sqlx::query_scalar("INSERT INTO users (email, api_key, credits) VALUES (?, ?, ?) RETURNING id")
+                .bind("meter@example.com")
+                .bind(keys::mint().stored)
+                .bind(credits)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            let (key, _) = keys::create_key(&db, user_id, "test", keys::DEFAULT_SCOPES, None).await.unwrap();
+            let state = AppState::for_tests(db, Arc::new(ScriptedRpc::new("http://mock")));
+            Self { state, user_id, key }
+        }
+
+        async fn call(&self, path: &str) -> Response {
+            let app = Router::new()
+                .route("/api/v1/metrics/ok", get(|| async { "ok" }))
+                .route("/api/v1/metrics/broken", get(|| async { StatusCode::BAD_GATEWAY }))
+                .route_layer(middleware::from_fn_with_state(self.state.clone(), meter))
+                .with_state(self.state.clone());
+            let req = axum::http::Request::get(path)
+                .header("authorization", format!("Bearer {}", self.key))
+                .body(Body::empty())
+                .unwrap();
+            app.oneshot(req).await.unwrap()
+        }
+
+        async fn balance(&self) -> (i64, i64) {
+            sqlx::query_as("SELECT credits, requests FROM users WHERE id = ?")
+                .bind(self.user_id)
+                .fetch_one(&self.state.db)
+                .await
+                .unwrap()
+        }
+
+        async fn ledger(&self) -> Vec<(Option<i64>, String, i64, i64)> {
+            sqlx::query_as("SELECT api_key_id, endpoint, cost, status_code FROM usage_ledger ORDER BY id")
+                .fetch_all(&self.state.db)
+                .await
+                .unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn successful_calls_are_charged_and_recorded() {
+        let f = Fixture::new(5).await;
+        assert_eq!(f.call("/api/v1/metrics/ok").await.status(), StatusCode::OK);
+        assert_eq!(f.balance().await, (4, 1));
+
+        let ledger = f.ledger().await;
+        assert_eq!(ledger.len(), 1);
+        assert!(ledger[0].0.is_some());
+        assert_eq!((ledger[0].1.as_str(), ledger[0].2, ledger[0].3), ("/api/v1/metrics/ok", 1, 200));
+    }
+
+    #[tokio::test]
+    async fn failed_calls_are_refunded_but_recorded() {
+        let f = Fixture::new(5).await;
+        assert_eq!(f.call("/api/v1/metrics/broken").await.status(), StatusCode::BAD_GATEWAY);
+        assert_eq!(f.balance().await, (5, 0));
+
+        let ledger = f.ledger().await;
+        assert_eq!(ledger.len(), 1);
+        assert_eq!((ledger[0].2, ledger[0].3), (0, 502));
+    }
+
+    #[tokio::test]
+    async fn exhausted_balance_gets_402() {
+        let f = Fixture::new(0).await;
+        let response = f.call("/api/v1/metrics/ok").await;
+        assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "CREDITS_EXHAUSTED");
+        assert_eq!(body["credits_required"], 1);
+        assert_eq!(body["credits_remaining"], 0);
+
+        // Nothing was debited or counted, and a rejected call is not a ledger line.
+        assert_eq!(f.balance().await, (0, 0));
+        assert!(f.ledger().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn debit_never_goes_below_zero() {
+        let f = Fixture::new(2).await;
+        assert!(debit(&f.state.db, f.user_id, 2).await.unwrap());
+        assert!(!debit(&f.state.db, f.user_id, 1).await.unwrap());
+        assert_eq!(f.balance().await, (0, 1));
+    }
+}
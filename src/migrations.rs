@@ -0,0 +1,188 @@
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+use std::fmt;
+
+/// An embedded, ordered schema change. Never edit one that has shipped; add a new one.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "initial_schema", sql: include_str!("../migrations/0001_initial_schema.sql") },
+    Migration { version: 2, name: "metric_history", sql: include_str!("../migrations/0002_metric_history.sql") },
+];
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.sql.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+#[derive(Debug)]
+pub enum MigrateError {
+    Db(sqlx::Error),
+    /// An applied migration's SQL differs from what this binary embeds.
+    ChecksumMismatch { version: i64, name: String },
+    /// The database was migrated by a newer binary; running would risk corrupting it.
+    DatabaseNewer { database: i64, binary: i64 },
+}
+
+impl fmt::Display for MigrateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrateError::Db(e) => write!(f, "database error: {}", e),
+            MigrateError::ChecksumMismatch { version, name } => {
+                write!(f, "migration {} ({}) was modified after being applied", version, name)
+            }
+            MigrateError::DatabaseNewer { database, binary } => write!(
+                f,
+                "database schema is at version {} but this binary only knows up to {}; refusing to start",
+                database, binary
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MigrateError {}
+
+impl From<sqlx::Error> for MigrateError {
+    fn from(e: sqlx::Error) -> Self {
+        MigrateError::Db(e)
+    }
+}
+
+/// Verifies applied migrations and returns the ones still pending. Read-only.
+pub async fn pending(pool: &Pool<Sqlite>) -> Result<Vec<&'static Migration>, MigrateError> {
+    let tracked: Option<String> = sqlx::query_scalar("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'schema_migrations'")
+        .fetch_optional(pool)
+        .await?;
+
+    let applied: Vec<(i64, String, String)> = if tracked.is_some() {
+        sqlx::query_as("SELECT version, name, checksum FROM schema_migrations ORDER BY version")
+            .fetch_all(pool)
+            .await?
+    } else {
+        Vec::new()
+    };
+
+    if let Some((database, _, _)) = applied.last() {
+        if *database > latest_version() {
+            return Err(MigrateError::DatabaseNewer { database: *database, binary: latest_version() });
+        }
+    }
+
+    for (version, name, checksum) in &applied {
+        let known = MIGRATIONS.iter().find(|m| m.version == *version);
+        if known.map_or(true, |m| m.checksum() != *checksum) {
+            return Err(MigrateError::ChecksumMismatch { version: *version, name: name.clone() });
+        }
+    }
+
+    Ok(MIGRATIONS.iter().filter(|m| !applied.iter().any(|(v, _, _)| *v == m.version)).collect())
+}
+
+/// Applies pending migrations in order, each in its own transaction.
+pub async fn run(pool: &Pool<Sqlite>) -> Result<(), MigrateError> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+    )
+    .execute(pool)
+    .await?;
+
+    for migration in pending(pool).await? {
+        let mut tx = pool.begin().await?;
+
+        // raw_sql: migration files hold several statements
+        sqlx::raw_sql(migration.sql).execute(&mut *tx).await?;
+        sqlx::query("INSERT INTO schema_migrations (version, name, checksum) VALUES (?, ?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(migration.checksum())
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        tracing::info!(version = migration.version, name = migration.name, "applied migration");
+    }
+    Ok(())
+}
+
+/// Prints what `run` would do; used by `--migrate-dry-run`.
+pub async fn dry_run(pool: &Pool<Sqlite>) -> Result<(), MigrateError> {
+    let pending = pending(pool).await?;
+    if pending.is_empty() {
+        println!(">>> Schema is up to date (version {})", latest_version());
+    }
+    for m in pending {
+        println!(">>> PENDING {:04}_{} (sha256 {})", m.version, m.name, m.checksum());
+        println!("{}", m.sql);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn empty_db() -> Pool<Sqlite> {
+        SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap()
+    }
+
+    async fn applied(pool: &Pool<Sqlite>) -> Vec<i64> {
+        sqlx::query_scalar("SELECT version FROM schema_migrations ORDER BY version").fetch_all(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn runs_everything_once() {
+        let pool = empty_db().await;
+        assert_eq!(pending(&pool).await.unwrap().len(), MIGRATIONS.len());
+
+        run(&pool).await.unwrap();
+        let all: Vec<i64> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(applied(&pool).await, all);
+        assert!(pending(&pool).await.unwrap().is_empty());
+
+        run(&pool).await.unwrap();
+        assert_eq!(applied(&pool).await, all);
+    }
+
+    #[tokio::test]
+    async fn edited_migrations_are_detected() {
+        let pool = empty_db().await;
+        run(&pool).await.unwrap();
+        sqlx::query("UPDATE schema_migrations SET checksum = 'edited' WHERE version = 1").execute(&pool).await.unwrap();
+
+        assert!(matches!(pending(&pool).await, Err(MigrateError::ChecksumMismatch { version: 1, .. })));
+        assert!(matches!(run(&pool).await, Err(MigrateError::ChecksumMismatch { version: 1, .. })));
+    }
+
+    #[tokio::test]
+    async fn refuses_a_newer_database() {
+        let pool = empty_db().await;
+        run(&pool).await.unwrap();
+        let future = latest_version() + 1;
+        sqlx::query("INSERT INTO schema_migrations (version, name, checksum) VALUES (?, 'from_the_future', 'x')")
+            .bind(future)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        match run(&pool).await {
+            Err(MigrateError::DatabaseNewer { database, binary }) => assert_eq!((database, binary), (future, latest_version())),
+            other => panic!("expected DatabaseNewer, got {:?}", other),
+        }
+        assert_eq!(applied(&pool).await.last(), Some(&future));
+    }
+}
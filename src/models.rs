@@ -1,17 +1,21 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use chrono::{DateTime, Utc};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use sqlx::{Pool, Sqlite};
-use crate::engine::EngineMetrics;
+use crate::rpc_pool::RpcPool;
+use crate::rpc_source::RpcSource;
+use crate::stream::{MetricsHub, StreamLimiter};
 
 #[derive(Debug, FromRow, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
-    pub public_key: String,      
+    pub public_key: Option<String>,
+    pub email: Option<String>,
     pub api_key: String,         
     pub tier: String,           
     pub credits: i64,            
+    pub requests: i64,
     pub created_at: DateTime<Utc>,
     pub last_active: DateTime<Utc>,
 }
@@ -24,17 +28,50 @@ pub struct UserProfile {
     pub status: String,
 }
 
+#[derive(Debug, FromRow, Serialize)]
+pub struct ApiKey {
+    pub id: i64,
+    #[serde(skip)]
+    pub user_id: i64,
+    pub key_id: String,
+    #[serde(skip)]
+    pub key_hash: String,
+    pub label: String,
+    pub scopes: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked: bool,
+}
+
 #[derive(Debug, FromRow)]
 pub struct PaymentTx {
     pub id: i64,
     pub user_id: i64,
     pub signature: String,       
     pub amount_sol: f64,
+    pub plan: String,
     pub status: String,          
+    pub failure_reason: Option<String>,
+    pub attempts: i64,
+    pub next_check_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 #[derive(Clone)]
 pub struct AppState {
     pub db: Pool<Sqlite>,
-    pub metrics: Arc<Mutex<EngineMetrics>>,
+    pub metrics: Arc<MetricsHub>,
+    pub streams: Arc<StreamLimiter>,
+    pub rpc: Arc<dyn RpcSource>,
+    pub rpc_pool: Arc<RpcPool>,
+}
+
+#[cfg(test)]
+impl AppState {
+    /// State over `db` where every RPC call, pooled or not, goes to `rpc`.
+    pub fn for_tests(db: Pool<Sqlite>, rpc: Arc<dyn RpcSource>) -> Arc<Self> {
+        let rpc_pool = Arc::new(RpcPool::from_sources(vec![rpc.clone()], std::time::Duration::from_secs(1)));
+        Arc::new(Self { db, metrics: Arc::default(), streams: Arc::default(), rpc, rpc_pool })
+    }
 }
@@ -0,0 +1,407 @@
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde_json::json;
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+use crate::keys;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// Token bucket parameters: `burst` requests at once, refilled at `per_sec`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    pub per_sec: f64,
+    pub burst: f64,
+}
+
+impl Quota {
+    pub const fn new(per_sec: f64, burst: f64) -> Self {
+        Self { per_sec, burst }
+    }
+}
+
+/// Unauthenticated callers are limited per IP.
+pub const ANONYMOUS_QUOTA: Quota = Quota::new(2.0, 5.0);
+
+pub fn quota_for_tier(tier: &str) -> Quota {
+    match tier.to_ascii_lowercase().as_str() {
+        "pro" => Quota::new(25.0, 50.0),
+        "enterprise" => Quota::new(100.0, 200.0),
+        _ => Quota::new(5.0, 10.0),
+    }
+}
+
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u64,
+    pub remaining: u64,
+    /// Seconds until the bucket is full again.
+    pub reset_secs: u64,
+    /// Seconds until the next request would be allowed; 0 when allowed.
+    pub retry_after_secs: u64,
+}
+
+impl Decision {
+    fn apply_headers(&self, headers: &mut HeaderMap) {
+        headers.insert("x-ratelimit-limit", HeaderValue::from(self.limit));
+        headers.insert("x-ratelimit-remaining", HeaderValue::from(self.remaining));
+        headers.insert("x-ratelimit-reset", HeaderValue::from(self.reset_secs));
+        if !self.allowed {
+            headers.insert("retry-after", HeaderValue::from(self.retry_after_secs));
+        }
+    }
+}
+
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// In-memory token buckets keyed by caller id.
+pub struct RateLimiter<C: Clock = SystemClock> {
+    clock: C,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl<C: Clock> RateLimiter<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self { clock, buckets: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn check(&self, key: &str, quota: Quota) -> Decision {
+        let now = self.clock.now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() > PRUNE_THRESHOLD {
+            // A bucket that would be full again carries no state worth keeping.
+            let full_after = Duration::from_secs_f64(quota.burst / quota.per_sec);
+            buckets.retain(|_, b| now.duration_since(b.updated) < full_after);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket { tokens: quota.burst, updated: now });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * quota.per_sec).min(quota.burst);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        Decision {
+            allowed,
+            limit: quota.burst as u64,
+            remaining: bucket.tokens.floor() as u64,
+            reset_secs: ((quota.burst - bucket.tokens) / quota.per_sec).ceil() as u64,
+            retry_after_secs: if allowed { 0 } else { ((1.0 - bucket.tokens) / quota.per_sec).ceil().max(1.0) as u64 },
+        }
+    }
+}
+
+/// Decides which bucket a request draws from and with what quota. Implementations pull
+/// what they need out of the request synchronously and do any lookups in the future.
+pub trait Classify: Clone + Send + Sync + 'static {
+    fn classify(&self, req: &Request) -> BoxFuture<(String, Quota)>;
+}
+
+/// Buckets by API key id with the owner's tier quota once the key verifies, or by client
+/// IP otherwise. Key ids are public, so an id alone must never select a bucket: anyone
+/// could drain another customer's quota or mint fresh buckets with made-up ids.
+#[derive(Clone)]
+pub struct ApiKeyClassifier {
+    db: Pool<Sqlite>,
+    /// key id → (owner's tier, stored `<key_id>:<hmac>`, when it was looked up)
+    keys: Arc<Mutex<HashMap<String, (String, String, Instant)>>>,
+}
+
+const TIER_CACHE_TTL: Duration = Duration::from_secs(60);
+
+impl ApiKeyClassifier {
+    pub fn new(db: Pool<Sqlite>) -> Self {
+        Self { db, keys: Arc::new(Mutex::new(HashMap::new())) }
+    }
+}
+
+impl Classify for ApiKeyClassifier {
+    fn classify(&self, req: &Request) -> BoxFuture<(String, Quota)> {
+        let query_key = req.uri().query().and_then(|q| {
+            q.split('&').find_map(|kv| kv.strip_prefix("key=")).map(str::to_string)
+        });
+        let presented = keys::presented_key(req.headers(), query_key);
+        let ip = req.extensions().get::<ConnectInfo<SocketAddr>>()
+            .map(|ci| ci.0.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        let db = self.db.clone();
+        let cache = self.keys.clone();
+        Box::pin(async move {
+            let anonymous = (format!("ip:{}", ip), ANONYMOUS_QUOTA);
+            // `key_id` only accepts bs58 ids, so the lookup below never sees a pattern.
+            let Some((key, key_id)) = presented.as_deref().and_then(|k| Some((k, keys::key_id(k)?))) else {
+                return anonymous;
+            };
+
+            let cached = cache.lock().unwrap().get(key_id)
+                .filter(|(_, _, at)| at.elapsed() < TIER_CACHE_TTL)
+                .map(|(tier, stored, _)| (tier.clone(), stored.clone()));
+
+            let found = match cached {
+                Some(found) => Some(found),
+                None => {
+                    let found: Option<(String, String)> = sqlx::query_as(
+                        "SELECT u.tier, k.key_hash FROM api_keys k JOIN users u ON u.id = k.user_id WHERE k.key_id = ?
+                         UNION ALL
+                         SELECT tier, api_key FROM users WHERE substr(api_key, 1, length(?) + 1) = ? || ':'
+                         LIMIT 1",
+                    )
+                    .bind(key_id)
+                    .bind(key_id)
+                    .bind(key_id)
+                    .fetch_optional(&db)
+                    .await
+                    .ok()
+                    .flatten();
+                    if let Some((tier, stored)) = &found {
+                        cache.lock().unwrap().insert(key_id.to_string(), (tier.clone(), stored.clone(), Instant::now()));
+                    }
+                    found
+                }
+            };
+
+            match found {
+                Some((tier, stored)) if keys::verify(key, &stored) => (format!("key:{}", key_id), quota_for_tier(&tier)),
+                // Unknown ids and wrong secrets count against the caller's IP like anonymous traffic.
+                _ => anonymous,
+            }
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitLayer<K: Classify, C: Clock = SystemClock> {
+    limiter: Arc<RateLimiter<C>>,
+    classifier: K,
+}
+
+impl<K: Classify> RateLimitLayer<K, SystemClock> {
+    pub fn new(classifier: K) -> Self {
+        Self { limiter: Arc::new(RateLimiter::new()), classifier }
+    }
+}
+
+impl<K: Classify, C: Clock> RateLimitLayer<K, C> {
+    pub fn with_limiter(classifier: K, limiter: Arc<RateLimiter<C>>) -> Self {
+        Self { limiter, classifier }
+    }
+}
+
+impl<S, K: Classify, C: Clock> Layer<S> for RateLimitLayer<K, C> {
+    type Service = RateLimit<S, K, C>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit { inner, limiter: self.limiter.clone(), classifier: self.classifier.clone() }
+    }
+}
+
+pub struct RateLimit<S, K, C: Clock> {
+    inner: S,
+    limiter: Arc<RateLimiter<C>>,
+    classifier: K,
+}
+
+impl<S: Clone, K: Clone, C: Clock> Clone for RateLimit<S, K, C> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), limiter: self.limiter.clone(), classifier: self.classifier.clone() }
+    }
+}
+
+impl<S, K, C> Service<Request> for RateLimit<S, K, C>
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    K: Classify,
+    C: Clock,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        // Take the service that was driven to readiness, leave a fresh clone behind.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        let classify = self.classifier.classify(&req);
+
+        Box::pin(async move {
+            let (key, quota) = classify.await;
+            let decision = limiter.check(&key, quota);
+
+            if !decision.allowed {
+                let mut res = (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(json!({ "error": "Rate limit exceeded", "retry_after": decision.retry_after_secs })),
+                )
+                    .into_response();
+                decision.apply_headers(res.headers_mut());
+                return Ok(res);
+            }
+
+            let mut res = inner.call(req).await?;
+            decision.apply_headers(res.headers_mut());
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use tower::ServiceExt;
+
+    #[derive(Clone)]
+    struct MockClock(Arc<Mutex<Instant>>);
+
+    impl MockClock {
+        fn new() -> Self {
+            Self(Arc::new(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[derive(Clone)]
+    struct Fixed(Quota);
+
+    impl Classify for Fixed {
+        fn classify(&self, req: &Request) -> BoxFuture<(String, Quota)> {
+            let key = req.headers().get("x-caller").and_then(|v| v.to_str().ok()).unwrap_or("anon").to_string();
+            let quota = self.0;
+            Box::pin(async move { (key, quota) })
+        }
+    }
+
+    #[test]
+    fn burst_then_reject() {
+        let limiter = RateLimiter::with_clock(MockClock::new());
+        let quota = Quota::new(5.0, 3.0);
+
+        for remaining in [2, 1, 0] {
+            let d = limiter.check("a", quota);
+            assert!(d.allowed);
+            assert_eq!(d.remaining, remaining);
+        }
+
+        let d = limiter.check("a", quota);
+        assert!(!d.allowed);
+        assert_eq!(d.retry_after_secs, 1);
+    }
+
+    #[test]
+    fn refills_with_time() {
+        let clock = MockClock::new();
+        let limiter = RateLimiter::with_clock(clock.clone());
+        let quota = Quota::new(2.0, 2.0);
+
+        assert!(limiter.check("a", quota).allowed);
+        assert!(limiter.check("a", quota).allowed);
+        assert!(!limiter.check("a", quota).allowed);
+
+        clock.advance(Duration::from_millis(500));
+        assert!(limiter.check("a", quota).allowed);
+        assert!(!limiter.check("a", quota).allowed);
+
+        // Never refills past the burst size.
+        clock.advance(Duration::from_secs(60));
+        let d = limiter.check("a", quota);
+        assert!(d.allowed);
+        assert_eq!(d.remaining, 1);
+        assert_eq!(d.reset_secs, 1);
+    }
+
+    #[test]
+    fn keys_are_independent() {
+        let limiter = RateLimiter::with_clock(MockClock::new());
+        let quota = Quota::new(1.0, 1.0);
+
+        assert!(limiter.check("a", quota).allowed);
+        assert!(!limiter.check("a", quota).allowed);
+        assert!(limiter.check("b", quota).allowed);
+    }
+
+    #[test]
+    fn tier_quotas() {
+        assert_eq!(quota_for_tier("free"), Quota::new(5.0, 10.0));
+        assert_eq!(quota_for_tier("Pro"), Quota::new(25.0, 50.0));
+        assert_eq!(quota_for_tier("whatever"), quota_for_tier("free"));
+    }
+
+    #[tokio::test]
+    async fn layer_sets_headers_and_429() {
+        let clock = MockClock::new();
+        let limiter = Arc::new(RateLimiter::with_clock(clock.clone()));
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .layer(RateLimitLayer::with_limiter(Fixed(Quota::new(1.0, 1.0)), limiter));
+
+        let req = || Request::builder().uri("/").header("x-caller", "bot").body(Body::empty()).unwrap();
+
+        let res = app.clone().oneshot(req()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["x-ratelimit-limit"], "1");
+        assert_eq!(res.headers()["x-ratelimit-remaining"], "0");
+
+        let res = app.clone().oneshot(req()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers()["retry-after"], "1");
+
+        clock.advance(Duration::from_secs(1));
+        let res = app.oneshot(req()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,296 @@
+use chrono::{Duration as ChronoDuration, Utc};
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::time::{sleep, Duration};
+use crate::billing::{self, Actor, Verification};
+use crate::models::{AppState, PaymentTx};
+
+#[derive(Debug, Clone)]
+pub struct ReconcilerConfig {
+    /// How often the pending queue is scanned.
+    pub interval: Duration,
+    /// Pending payments older than this are marked `expired`.
+    pub expiry: ChronoDuration,
+    /// First retry delay; doubles per attempt up to `max_backoff`.
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    pub batch_size: i64,
+}
+
+impl Default for ReconcilerConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+            expiry: ChronoDuration::minutes(30),
+            base_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(300),
+            batch_size: 50,
+        }
+    }
+}
+
+impl ReconcilerConfig {
+    pub fn backoff(&self, attempts: i64) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.clamp(0, 16) as u32);
+        self.base_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// Re-checks `pending` payments against the chain until they confirm, fail or expire.
+pub async fn start_payment_reconciler(state: Arc<AppState>, config: ReconcilerConfig) {
+    tracing::info!(interval = ?config.interval, "payment reconciler started");
+
+    loop {
+        if let Err(e) = reconcile_once(&state, &config).await {
+            tracing::error!(error = %e, "reconciler pass failed");
+        }
+        sleep(config.interval).await;
+    }
+}
+
+pub async fn reconcile_once(state: &AppState, config: &ReconcilerConfig) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+
+    let due: Vec<PaymentTx> = sqlx::query_as(
+        "SELECT * FROM payment_tx
+         WHERE status = 'pending' AND (next_check_at IS NULL OR next_check_at <= datetime(?))
+         ORDER BY created_at LIMIT ?",
+    )
+    .bind(now)
+    .bind(config.batch_size)
+    .fetch_all(&state.db)
+    .await?;
+
+    for payment in due {
+        let wallet: Option<String> = sqlx::query_scalar("SELECT public_key FROM users WHERE id = ?")
+            .bind(payment.user_id)
+            .fetch_optional(&state.db)
+            .await?;
+
+        let outcome = match wallet.as_deref().map(Pubkey::from_str) {
+            Some(Ok(payer)) => billing::check_payment(state.rpc.as_ref(), &payment, &payer).await,
+            _ => Verification::Failed("account has no valid wallet".to_string()),
+        };
+
+        match outcome {
+            Verification::Confirmed => {
+                if billing::confirm_payment(&state.db, &payment, Actor::Reconciler).await? {
+                    tracing::info!(signature = %payment.signature, "payment confirmed");
+                }
+            }
+            Verification::Failed(reason) => {
+                billing::close_payment(&state.db, &payment, "failed", &reason, Actor::Reconciler).await?;
+                tracing::info!(signature = %payment.signature, reason = %reason, "payment failed");
+            }
+            Verification::Pending if payment.created_at + config.expiry <= now => {
+                let reason = format!("not finalized within {} minutes", config.expiry.num_minutes());
+                billing::close_payment(&state.db, &payment, "expired", &reason, Actor::Reconciler).await?;
+                tracing::info!(signature = %payment.signature, "payment expired");
+            }
+            Verification::Pending => {
+                let next = now + ChronoDuration::from_std(config.backoff(payment.attempts)).unwrap_or(config.expiry);
+                sqlx::query("UPDATE payment_tx SET attempts = attempts + 1, next_check_at = datetime(?) WHERE id = ?")
+                    .bind(next)
+                    .bind(payment.id)
+                    .execute(&state.db)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::expected_memo;
+    use crate::config;
+    use crate::db::memory_pool;
+    use crate::rpc_source::mock::ScriptedRpc;
+    use serde_json::{json, Value};
+    use solana_sdk::{native_token::LAMPORTS_PER_SOL, signature::Signature};
+
+    struct Fixture {
+        state: Arc<AppState>,
+        rpc: Arc<ScriptedRpc>,
+        payer: Pubkey,
+        user_id: i64,
+        payment_id: i64,
+    }
+
+    impl Fixture {
+        /// A `credits_10k` payment submitted `age` ago by a wallet user.
+        async fn new(age: ChronoDuration) -> Self {
+            config::init_for_tests();
+            let db = memory_pool().await;
+            let payer = Pubkey::new_unique();
+            let user_id: i64 = sqlx::query_scalar("INSERT INTO users (public_key, api_key) VALUES (?, 'placeholder:00') RETURNING id")
+                .bind(payer.to_string())
+                .fetch_one(&db)
+                .await
+                .unwrap();
+            let payment_id: i64 = sqlx::query_scalar(
+                "INSERT INTO payment_tx (user_id, signature, amount_sol, plan, created_at)
+                 VALUES (?, ?, 0.05, 'credits_10k', datetime(?)) RETURNING id",
+            )
+            .bind(user_id)
+            .bind(Signature::new_unique().to_string())
+            .bind(Utc::now() - age)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+
+            let rpc = Arc::new(ScriptedRpc::new("http://mock"));
+            let state = AppState::for_tests(db, rpc.clone());
+            Self { state, rpc, payer, user_id, payment_id }
+        }
+
+        fn status(&self, status: Value) -> &Self {
+            self.rpc.push("getSignatureStatuses", json!({ "context": { "slot": 1_000 }, "value": [status] }));
+            self
+        }
+
+        fn finalized(&self, err: Option<&str>) -> &Self {
+            let status = match err {
+                Some(e) => json!({ "Err": e }),
+                None => json!({ "Ok": null }),
+            };
+            self.status(json!({
+                "slot": 990,
+                "confirmations": null,
+                "status": status,
+                "err": err,
+                "confirmationStatus": "finalized",
+            }))
+        }
+
+        fn transfer(&self, lamports: u64) -> &Self {
+            let (payer, treasury) = (self.payer.to_string(), billing::treasury().unwrap().to_string());
+            self.rpc.push("getTransaction", json!({
+                "slot": 990,
+                "blockTime": 1_700_000_000,
+                "transaction": {
+                    "signatures": [Signature::new_unique().to_string()],
+                    "message": {
+                        "accountKeys": [
+                            { "pubkey": payer, "writable": true, "signer": true, "source": "transaction" },
+                            { "pubkey": treasury, "writable": true, "signer": false, "source": "transaction" },
+                        ],
+                        "recentBlockhash": "11111111111111111111111111111111",
+                        "instructions": [
+                            {
+                                "program": "system",
+                                "programId": "11111111111111111111111111111111",
+                                "parsed": { "type": "transfer", "info": { "source": payer, "destination": treasury, "lamports": lamports } },
+                            },
+                            {
+                                "program": "spl-memo",
+                                "programId": "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr",
+                                "parsed": expected_memo(self.user_id, "credits_10k"),
+                            },
+                        ],
+                    },
+                },
+                "meta": null,
+            }));
+            self
+        }
+
+        async fn reconcile(&self) {
+            reconcile_once(&self.state, &ReconcilerConfig::default()).await.unwrap();
+        }
+
+        async fn payment(&self) -> PaymentTx {
+            sqlx::query_as("SELECT * FROM payment_tx WHERE id = ?")
+                .bind(self.payment_id)
+                .fetch_one(&self.state.db)
+                .await
+                .unwrap()
+        }
+
+        async fn audit(&self) -> Vec<(Option<String>, String, String)> {
+            sqlx::query_as("SELECT from_status, to_status, actor FROM payment_audit WHERE payment_id = ? ORDER BY id")
+                .bind(self.payment_id)
+                .fetch_all(&self.state.db)
+                .await
+                .unwrap()
+        }
+    }
+
+    fn closed(to: &str) -> Vec<(Option<String>, String, String)> {
+        vec![(Some("pending".to_string()), to.to_string(), "reconciler".to_string())]
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let config = ReconcilerConfig::default();
+        assert_eq!(config.backoff(0), Duration::from_secs(10));
+        assert_eq!(config.backoff(3), Duration::from_secs(80));
+        assert_eq!(config.backoff(40), Duration::from_secs(300));
+    }
+
+    #[tokio::test]
+    async fn pending_to_confirmed_credits_once() {
+        let f = Fixture::new(ChronoDuration::zero()).await;
+        f.finalized(None).transfer(LAMPORTS_PER_SOL / 20);
+
+        f.reconcile().await;
+        f.reconcile().await;
+
+        assert_eq!(f.payment().await.status, "confirmed");
+        let credits: i64 = sqlx::query_scalar("SELECT credits FROM users WHERE id = ?")
+            .bind(f.user_id)
+            .fetch_one(&f.state.db)
+            .await
+            .unwrap();
+        assert_eq!(credits, 1_000 + 10_000);
+        assert_eq!(f.audit().await, closed("confirmed"));
+    }
+
+    #[tokio::test]
+    async fn pending_to_failed_on_chain_error() {
+        let f = Fixture::new(ChronoDuration::zero()).await;
+        f.finalized(Some("AccountInUse"));
+
+        f.reconcile().await;
+
+        let payment = f.payment().await;
+        assert_eq!(payment.status, "failed");
+        assert!(payment.failure_reason.is_some_and(|r| r.contains("failed on-chain")));
+        assert_eq!(f.audit().await, closed("failed"));
+    }
+
+    #[tokio::test]
+    async fn pending_to_expired_after_the_window() {
+        let f = Fixture::new(ChronoDuration::minutes(31)).await;
+        f.status(Value::Null);
+
+        f.reconcile().await;
+
+        let payment = f.payment().await;
+        assert_eq!(payment.status, "expired");
+        assert_eq!(payment.failure_reason.as_deref(), Some("not finalized within 30 minutes"));
+        assert_eq!(f.audit().await, closed("expired"));
+    }
+
+    #[tokio::test]
+    async fn still_pending_backs_off() {
+        let f = Fixture::new(ChronoDuration::zero()).await;
+        f.status(Value::Null);
+
+        f.reconcile().await;
+        let payment = f.payment().await;
+        assert_eq!((payment.status.as_str(), payment.attempts), ("pending", 1));
+        let wait = payment.next_check_at.unwrap() - Utc::now();
+        assert!(wait > ChronoDuration::seconds(5) && wait <= ChronoDuration::seconds(10), "{:?}", wait);
+
+        // Not due again yet: the chain is not asked and nothing changes.
+        let calls = f.rpc.calls().len();
+        f.reconcile().await;
+        assert_eq!(f.rpc.calls().len(), calls);
+        assert_eq!(f.payment().await.attempts, 1);
+        assert!(f.audit().await.is_empty());
+    }
+}
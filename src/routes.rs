@@ -1,154 +1,237 @@
 use axum::{
-    extract::{State, Query},
-    response::{Html, IntoResponse, Json},
+    extract::{Form, State},
+    http::StatusCode,
+    middleware,
+    response::{Html, IntoResponse, Json, Redirect, Response},
+    routing::{delete, get, post},
+    Router,
 };
+use serde::Deserialize;
 use std::sync::Arc;
-use serde_json::json;
+use serde_json::{json, Value};
+use crate::engine::EngineMetrics;
 use crate::models::AppState;
+use crate::ratelimit::{ApiKeyClassifier, RateLimitLayer};
+use crate::{auth, billing, config, exporter, history, keys, metering, rpc_pool, stream, telemetry, ws};
+
+/// `with_metrics` also serves the Prometheus scrape on `/metrics`, for when there is no
+/// separate metrics listener.
+pub fn router(state: Arc<AppState>, with_metrics: bool) -> Router {
+    // Key-authenticated and charged per call; auth happens in the metering layer.
+    let metered = Router::new()
+        .route("/api/v1/stream", get(stream::api_stream))
+        .route("/api/v1/ws", get(ws::ws_handler))
+        .route("/api/v1/rpc/health", get(rpc_pool::rpc_health))
+        .route("/api/v1/metrics/history", get(history::metrics_history))
+        .route_layer(middleware::from_fn_with_state(state.clone(), metering::meter));
+
+    let api = Router::new()
+        .route("/api/metrics", get(get_metrics))
+        .route("/api/v1/status", get(get_status))
+        .route("/api/metrics/stream", get(stream::public_stream))
+        .route("/api/v1/auth/nonce", post(auth::issue_nonce))
+        .route("/api/v1/auth/verify", post(auth::verify_signature))
+        .route("/api/v1/keys", get(keys::list_keys).post(keys::create_key_handler))
+        .route("/api/v1/keys/:id", delete(keys::revoke_key))
+        .route("/api/v1/keys/:id/rotate", post(keys::rotate_key))
+        .route("/api/v1/billing/plans", get(billing::list_plans))
+        .route("/api/v1/billing/payments", get(billing::list_payments).post(billing::submit_payment))
+        .route("/api/v1/billing/usage", get(metering::usage_report))
+        .route("/api/v1/admin/config", get(config::admin_config))
+        .merge(metered)
+        .layer(RateLimitLayer::new(ApiKeyClassifier::new(state.db.clone())));
+
+    let mut app = Router::new()
+        .route("/", get(landing_page))
+        .route("/login", get(login_page))
+        .route("/register", get(register_page).post(handle_register))
+        .route("/dashboard", get(dashboard_page))
+        .merge(api)
+        .layer(middleware::from_fn(exporter::track_http));
+    // Added after `track_http` so scrapes don't count themselves, but still traced.
+    if with_metrics {
+        app = app.route("/metrics", get(exporter::scrape));
+    }
+
+    telemetry::trace_http(app).with_state(state)
+}
 
 pub async fn get_metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    let metrics = state.metrics.lock().unwrap();
-    
-    Json(json!({
+    Json(envelope(metrics_data(&state.metrics.latest())))
+}
+
+/// The engine's status with its reason, and the recent transitions (oldest first).
+pub async fn get_status(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let m = state.metrics.latest();
+    Json(envelope(json!({
+        "status": m.status,
+        "reason": m.status_reason,
+        "detail": m.status_detail,
+        "since": m.status_since,
+        "consecutive_errors": m.consecutive_errors,
+        "last_success": m.last_success,
+        "transitions": state.metrics.transitions(),
+    })))
+}
+
+/// Response wrapper shared by every metrics endpoint; `data` is one snapshot or a series.
+pub fn envelope(data: Value) -> Value {
+    json!({
         "network": "solana-mainnet",
-        "data": {
-            "slot": metrics.slot,
-            "tps": metrics.tps,
-            "epoch": metrics.epoch,
-            "latency_ms": metrics.latency,
-            "status": metrics.status
-        },
+        "data": data,
         "timestamp": chrono::Utc::now().to_rfc3339()
-    }))
+    })
 }
 
-pub async fn landing_page() -> Html<&'static str> {
-    Html(r##"
-<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>ARKHEIONX | High-Frequency Intelligence</title>
-    <style>
-        :root { --bg: #050505; --card: #0F0F0F; --accent: #00FF9D; --text: #EAEAEA; --dim: #666; }
-        body { background: var(--bg); color: var(--text); font-family: 'Courier New', monospace; margin: 0; display: flex; flex-direction: column; min-height: 100vh; }
-        .nav { padding: 20px 40px; border-bottom: 1px solid #222; display: flex; justify-content: space-between; align-items: center; background: rgba(5,5,5,0.9); backdrop-filter: blur(10px); position: sticky; top: 0; }
-        .logo { font-weight: bold; font-size: 1.5rem; color: #fff; letter-spacing: -1px; text-decoration: none; }
-        .logo span { color: var(--accent); }
-        .hero { flex: 1; display: flex; flex-direction: column; align-items: center; justify-content: center; text-align: center; padding: 60px 20px; }
-        h1 { font-size: 3.5rem; line-height: 1; margin-bottom: 20px; letter-spacing: -2px; }
-        .btn { padding: 12px 30px; font-weight: bold; text-decoration: none; border-radius: 4px; transition: 0.2s; cursor: pointer; border: none; font-family: inherit; }
-        .btn-glow { background: var(--accent); color: #000; box-shadow: 0 0 20px rgba(0, 255, 157, 0.2); }
-        .btn-glow:hover { box-shadow: 0 0 40px rgba(0, 255, 157, 0.5); transform: scale(1.05); }
-        .terminal { background: var(--card); border: 1px solid #333; padding: 20px; border-radius: 8px; margin-top: 50px; width: 100%; max-width: 600px; text-align: left; position: relative; overflow: hidden; }
-        .terminal::before { content: ''; position: absolute; top: 0; left: 0; width: 100%; height: 2px; background: linear-gradient(90deg, transparent, var(--accent), transparent); animation: scan 2s infinite; }
-        @keyframes scan { 0% { left: -100%; } 100% { left: 100%; } }
-        .stat-row { display: flex; justify-content: space-between; margin-bottom: 10px; font-size: 0.9rem; }
-        .val { color: var(--accent); font-weight: bold; }
-    </style>
-</head>
-<body>
-    <nav>
-        <a href="/" class="logo">ARKHEION<span>X</span></a>
-        <div>
-            <a href="/login" style="color: #fff; text-decoration: none; margin-right: 20px;">Console</a>
-            <a href="/dashboard" class="btn btn-glow">Connect Wallet</a>
-        </div>
-    </nav>
-    
-    <div class="hero">
-        <div style="color: var(--dim); margin-bottom: 20px;">V3.0 // MAINNET BETA</div>
-        <h1>THE NERVOUS SYSTEM<br>OF SOLANA DEFI</h1>
-        <p style="color: var(--dim); max-width: 500px; margin-bottom: 40px;">
-            Institutional-grade RPC intelligence. Detect liquidations, whale movements, and mempool opportunities in milliseconds.
-        </p>
-        
-        <div class="terminal">
-            <div style="border-bottom: 1px solid #333; padding-bottom: 10px; margin-bottom: 15px; display: flex; justify-content: space-between;">
-                <span>LIVE FEED // US-EAST</span>
-                <span style="color: var(--accent);">● ONLINE</span>
-            </div>
-            <div class="stat-row"><span>SLOT HEIGHT</span><span class="val" id="s_slot">---</span></div>
-            <div class="stat-row"><span>TPS (AVG)</span><span class="val" id="s_tps">---</span></div>
-            <div class="stat-row"><span>EPOCH</span><span class="val" id="s_epoch">---</span></div>
-            <div class="stat-row"><span>LATENCY</span><span class="val" id="s_lat">---</span></div>
-        </div>
-    </div>
+pub fn metrics_data(metrics: &EngineMetrics) -> Value {
+    json!({
+        "slot": metrics.slot,
+        "slot_source": metrics.slot_source,
+        "root": metrics.root,
+        "cluster_slot": metrics.cluster_slot,
+        "slot_lag": metrics.slot_lag,
+        "lagging_nodes": metrics.lagging_nodes,
+        "forked_nodes": metrics.forked_nodes,
+        "tps": metrics.tps,
+        "tps_non_vote": metrics.tps_non_vote,
+        "tps_vote": metrics.tps_vote,
+        "tps_window_secs": metrics.tps_window_secs,
+        "tps_method": metrics.tps_method,
+        "slot_time_ms": metrics.slot_time_ms,
+        "skip_rate": metrics.skip_rate,
+        "epoch": metrics.epoch,
+        "latency_ms": metrics.latency,
+        "status": metrics.status,
+        "status_reason": metrics.status_reason,
+        "status_since": metrics.status_since,
+        "consecutive_errors": metrics.consecutive_errors,
+        "last_success": metrics.last_success
+    })
+}
 
-    <script>
-        async function sync() {
-            try {
-                let res = await fetch('/api/metrics');
-                let d = await res.json();
-                document.getElementById('s_slot').innerText = parseInt(d.data.slot).toLocaleString();
-                document.getElementById('s_tps').innerText = d.data.tps;
-                document.getElementById('s_epoch').innerText = "#" + d.data.epoch;
-                document.getElementById('s_lat').innerText = d.data.latency_ms + "ms";
-            } catch(e) {}
+#[derive(Deserialize)]
+pub struct AuthForm { email: String }
+
+pub async fn handle_register(State(state): State<Arc<AppState>>, Form(form): Form<AuthForm>) -> Response {
+    match register(&state, &form.email).await {
+        // Yang disimpan cuma hash-nya, key asli hanya tampil sekali di halaman ini
+        Ok(key) => key_created_page(&form.email, &key).into_response(),
+        // Email udah ada
+        Err(e) if e.as_database_error().is_some_and(|d| d.is_unique_violation()) => {
+            Redirect::to("/login?err=exists").into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "register failed");
+            (StatusCode::INTERNAL_SERVER_ERROR, Html("Could not create your account, please try again.")).into_response()
         }
-        setInterval(sync, 1000); // Update setiap 1 detik
-        sync();
-    </script>
-</body>
-</html>
-    "##)
+    }
+}
+
+/// Creates the user and their first key in `api_keys`, so it can be listed, rotated and
+/// revoked like any other. `users.api_key` only gets a placeholder hash nobody holds.
+async fn register(state: &AppState, email: &str) -> Result<String, sqlx::Error> {
+    let mut tx = state.db.begin().await?;
+    let user_id: i64 = sqlx::query_scalar("INSERT INTO users (email, api_key) VALUES (?, ?) RETURNING id")
+        .bind(email)
+        .bind(keys::mint().stored)
+        .fetch_one(&mut *tx)
+        .await?;
+    let (key, _) = keys::create_key(&mut *tx, user_id, "console", keys::DEFAULT_SCOPES, None).await?;
+    tx.commit().await?;
+    Ok(key)
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+fn key_created_page(email: &str, key: &str) -> Html<String> {
+    let email = html_escape(email);
+    let dashboard = format!("/dashboard?u={}", email);
+    Html(format!(r##"<!DOCTYPE html><html lang="en"><head><title>Your API Key</title><style>body{{background:#020202;color:#fff;font-family:sans-serif;height:100vh;display:grid;place-items:center}}.box{{width:480px;padding:40px;border:1px solid #333;border-radius:12px;text-align:center}}.key{{background:#000;border:1px solid #333;padding:12px;font-family:monospace;color:#00ff9d;word-break:break-all;margin:20px 0}}a.btn{{display:block;padding:12px;background:#fff;color:#000;font-weight:bold;text-decoration:none}}.logo{{font-weight:800;font-size:1.5rem;color:#fff;text-decoration:none;display:block;margin-bottom:30px}}span{{color:#00ff9d}}</style></head><body><div class="box"><a href="/" class="logo">ARKHEION<span>X</span></a><h2>API Key Created</h2><p style="color:#888">Copy it now. This is the only time it will be shown; we only store a hash.</p><code class="key" id="k">{key}</code><a class="btn" href="{dashboard}" onclick="sessionStorage.setItem('arkheion_key',document.getElementById('k').innerText)">I saved it, open console</a></div></body></html>"##))
+}
+
+pub async fn landing_page() -> Html<&'static str> {
+    Html(r##"<!DOCTYPE html><html lang="en"><head><title>ARKHEIONX | Enterprise</title><meta charset="UTF-8"><meta name="viewport" content="width=device-width,initial-scale=1"><link href="https://fonts.googleapis.com/css2?family=Inter:wght@400;600;800&family=JetBrains+Mono:wght@400;700&display=swap" rel="stylesheet"><style>:root{--bg:#020202;--sf:#0A0A0A;--bd:#222;--pr:#fff;--ac:#00FF9D}body{background:var(--bg);color:var(--pr);font-family:'Inter',sans-serif;margin:0}nav{padding:20px 5%;border-bottom:1px solid var(--bd);display:flex;justify-content:space-between;background:rgba(2,2,2,0.9);backdrop-filter:blur(10px);position:sticky;top:0;z-index:99}.logo{font-weight:800;font-size:1.2rem;letter-spacing:-1px;color:#fff;text-decoration:none}.hero{text-align:center;padding:100px 20px}.btn{padding:12px 30px;border-radius:6px;font-weight:600;text-decoration:none;display:inline-block}.btn-p{background:var(--pr);color:#000}.btn-s{border:1px solid var(--bd);color:var(--pr)}.grid{display:grid;grid-template-columns:repeat(auto-fit,minmax(250px,1fr));gap:30px;padding:5%;max-width:1200px;margin:0 auto}.card{background:var(--sf);border:1px solid var(--bd);padding:30px;border-radius:12px}footer{border-top:1px solid var(--bd);padding:50px;text-align:center;color:#666;font-size:0.8rem}.mockup{max-width:800px;margin:60px auto;background:#000;border:1px solid #333;border-radius:10px;overflow:hidden;box-shadow:0 0 50px rgba(0,255,157,0.1)}.m-head{padding:10px 20px;border-bottom:1px solid #333;display:flex;justify-content:space-between;font-family:'JetBrains Mono';font-size:0.75rem;color:#666}.m-body{padding:40px;display:grid;grid-template-columns:1fr 1fr;gap:20px;font-family:'JetBrains Mono'}</style></head><body>
+<nav><a href="/" class="logo">ARKHEION<span>X</span></a><div><a href="/login" class="btn btn-s" style="margin-right:10px">Console</a><a href="/register" class="btn btn-p">Get Access</a></div></nav>
+<div class="hero"><div style="color:var(--ac);font-weight:700;font-size:0.8rem;margin-bottom:20px;letter-spacing:1px">V3.0 ENTERPRISE</div><h1 style="font-size:4rem;letter-spacing:-2px;margin-bottom:20px;line-height:1.1">The Nervous System<br>of Solana DeFi.</h1><p style="color:#888;max-width:600px;margin:0 auto 40px;font-size:1.1rem">Milliseconds matter. Get direct RPC streams, liquidation signals, and mempool analytics.</p><div><a href="/register" class="btn btn-p">Start Building</a><a href="#pricing" class="btn btn-s" style="margin-left:10px">View Pricing</a></div><div class="mockup"><div class="m-head"><span>US-EAST-1</span><span style="color:var(--ac)">● SYSTEM ACTIVE</span></div><div class="m-body"><div style="text-align:left"><div style="color:#666;font-size:0.7rem">SLOT HEIGHT</div><div style="font-size:2rem" id="s">---</div></div><div style="text-align:left"><div style="color:#666;font-size:0.7rem">LATENCY</div><div style="font-size:2rem;color:var(--ac)" id="l">---</div></div></div></div></div>
+<div class="grid"><div class="card"><h3 style="margin-top:0">Global Nodes</h3><p style="color:#888">Distributed infrastructure ensuring < 50ms latency worldwide.</p></div><div class="card"><h3 style="margin-top:0">Security First</h3><p style="color:#888">Enterprise-grade encryption and API key management.</p></div><div class="card"><h3 style="margin-top:0">Data Persistence</h3><p style="color:#888">Historical data tracking with persistent storage layers.</p></div></div>
+<footer>&copy; 2026 ARKHEIONX SYSTEMS. ENGINEERED BY YUDISTIRA PUTRA DEV.</footer>
+<script>new EventSource('/api/metrics/stream').addEventListener('metrics',e=>{let d=JSON.parse(e.data).data;document.getElementById('s').innerText=d.slot.toLocaleString();document.getElementById('l').innerText=d.latency_ms+"ms"})</script>
+</body></html>"##)
+}
+
+pub async fn login_page() -> Html<&'static str> {
+    Html(r##"<!DOCTYPE html><html lang="en"><head><title>Login</title><style>body{background:#020202;color:#fff;font-family:sans-serif;height:100vh;display:grid;place-items:center}.box{width:350px;padding:40px;border:1px solid #333;border-radius:12px;text-align:center}input{width:100%;padding:12px;margin:10px 0;background:#0a0a0a;border:1px solid #333;color:#fff;box-sizing:border-box}button{width:100%;padding:12px;background:#fff;border:none;font-weight:bold;cursor:pointer;margin-top:10px}.logo{font-weight:800;font-size:1.5rem;color:#fff;text-decoration:none;display:block;margin-bottom:30px}span{color:#00ff9d}</style></head><body><div class="box"><a href="/" class="logo">ARKHEION<span>X</span></a><h2>Console Login</h2><button id="w">Sign in with Solana wallet</button><p id="e" style="color:#f33;font-size:0.8rem"></p><p style="color:#666;font-size:0.8rem;margin-top:20px">No account? <a href="/register" style="color:#fff">Get API Key</a></p></div>
+<script>
+const B58='123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz';
+function b58(bytes){let d=[];for(const x of bytes){let c=x;for(let i=0;i<d.length;i++){c+=d[i]<<8;d[i]=c%58;c=c/58|0}while(c){d.push(c%58);c=c/58|0}}let s='';for(const x of bytes){if(x)break;s+='1'}return s+d.reverse().map(i=>B58[i]).join('')}
+async function post(url,body){const r=await fetch(url,{method:'POST',headers:{'Content-Type':'application/json'},body:JSON.stringify(body)});const j=await r.json();if(!r.ok)throw Error(j.error);return j}
+document.getElementById('w').onclick=async()=>{try{
+    const w=window.solana;if(!w)throw Error('No Solana wallet found in this browser');
+    const pk=(await w.connect()).publicKey.toString();
+    const n=await post('/api/v1/auth/nonce',{public_key:pk});
+    const signed=await w.signMessage(new TextEncoder().encode(n.message),'utf8');
+    await post('/api/v1/auth/verify',{public_key:pk,nonce:n.nonce,signature:b58(signed.signature)});
+    window.location.href='/dashboard?u='+encodeURIComponent(pk);
+}catch(err){document.getElementById('e').innerText=err.message}};
+</script></body></html>"##)
+}
+
+pub async fn register_page() -> Html<&'static str> {
+    Html(r##"<!DOCTYPE html><html lang="en"><head><title>Register</title><style>body{background:#020202;color:#fff;font-family:sans-serif;height:100vh;display:grid;place-items:center}.box{width:350px;padding:40px;border:1px solid #333;border-radius:12px;text-align:center}input{width:100%;padding:12px;margin:10px 0;background:#0a0a0a;border:1px solid #333;color:#fff;box-sizing:border-box}button{width:100%;padding:12px;background:#fff;border:none;font-weight:bold;cursor:pointer;margin-top:10px}.logo{font-weight:800;font-size:1.5rem;color:#fff;text-decoration:none;display:block;margin-bottom:30px}span{color:#00ff9d}</style></head><body><div class="box"><a href="/" class="logo">ARKHEION<span>X</span></a><h2>Create API Key</h2><form action="/register" method="post"><input type="email" name="email" placeholder="Work Email" required><button>Generate Credentials</button></form><p style="color:#666;font-size:0.8rem;margin-top:20px">Existing user? <a href="/login" style="color:#fff">Login</a></p></div></body></html>"##)
 }
 
 pub async fn dashboard_page() -> Html<&'static str> {
-    Html(r##"
-<!DOCTYPE html>
-<html lang="en">
-<head>
-    <title>Dashboard | ARKHEIONX</title>
-    <style>
-        body { background: #050505; color: #fff; font-family: sans-serif; display: flex; height: 100vh; margin: 0; }
-        .sidebar { width: 250px; border-right: 1px solid #222; padding: 20px; display: flex; flex-direction: column; }
-        .content { flex: 1; padding: 40px; }
-        .logo { font-size: 1.2rem; font-weight: 800; margin-bottom: 40px; color: #fff; text-decoration: none; }
-        .menu-item { padding: 12px; color: #888; text-decoration: none; display: block; border-radius: 6px; margin-bottom: 5px; }
-        .menu-item:hover, .menu-item.active { background: #111; color: #fff; }
-        .card { background: #0F0F0F; border: 1px solid #222; padding: 25px; border-radius: 10px; margin-bottom: 20px; }
-        .key-box { background: #000; border: 1px solid #333; padding: 15px; font-family: monospace; color: #00FF9D; margin-top: 10px; word-break: break-all; }
-        h2 { margin-top: 0; font-size: 1.5rem; }
-        .badge { background: rgba(0,255,157,0.1); color: #00FF9D; padding: 5px 10px; border-radius: 20px; font-size: 0.8rem; border: 1px solid rgba(0,255,157,0.2); }
-    </style>
-</head>
-<body>
-    <div class="sidebar">
-        <a href="/" class="logo">ARKHEION<span style="color:#00FF9D">X</span></a>
-        <a href="#" class="menu-item active">Overview</a>
-        <a href="#" class="menu-item">API Keys</a>
-        <a href="#" class="menu-item">Billing (Web3)</a>
-        <a href="#" class="menu-item">Documentation</a>
-        <div style="margin-top: auto;">
-            <a href="/" class="menu-item" style="color: #f55;">Disconnect</a>
-        </div>
+    Html(r##"<!DOCTYPE html><html lang="en"><head><title>Terminal - ARKHEIONX</title><script src="https://cdn.jsdelivr.net/npm/chart.js"></script><style>body{background:#020202;color:#fff;font-family:sans-serif;margin:0;display:flex}.side{width:240px;border-right:1px solid #222;height:100vh;padding:20px;position:fixed}.main{margin-left:240px;padding:40px;width:100%}.logo{font-weight:800;font-size:1.2rem;color:#fff;text-decoration:none;display:block;margin-bottom:40px}span{color:#00ff9d}.menu a{display:block;color:#888;text-decoration:none;padding:10px;margin-bottom:5px;border-radius:4px}.menu a.active{background:#111;color:#fff}.card{background:#0a0a0a;border:1px solid #222;padding:20px;border-radius:8px}.key{background:#000;border:1px solid #333;padding:10px;font-family:monospace;color:#00ff9d;display:block;margin-top:10px;word-break:break-all}.grid{display:grid;grid-template-columns:repeat(3,1fr);gap:20px;margin-top:20px}canvas{width:100% !important;height:300px !important}</style></head><body>
+<div class="side"><a href="/" class="logo">ARKHEION<span>X</span></a><div class="menu"><a href="#" class="active">Overview</a><a href="#">Analytics</a><a href="#">Billing</a><a href="#">Settings</a><a href="/" style="margin-top:40px;color:#f33">Disconnect</a></div></div>
+<div class="main">
+    <div style="display:flex;justify-content:space-between;align-items:center;margin-bottom:30px"><h1>Overview</h1><div style="color:#666" id="u-email">...</div></div>
+    <div class="card" style="margin-bottom:30px">
+        <div style="font-size:0.7rem;color:#666;margin-bottom:10px">YOUR SECRET KEY</div>
+        <code class="key" id="apikey">...</code>
+    </div>
+    <div class="card">
+        <div style="font-size:0.7rem;color:#666;margin-bottom:20px">NETWORK THROUGHPUT (TPS)</div>
+        <canvas id="tpsChart"></canvas>
     </div>
-    <div class="content">
-        <div style="display:flex; justify-content:space-between; align-items:center; margin-bottom:30px;">
-            <h2>Console Overview</h2>
-            <div class="badge">TIER: FREE</div>
-        </div>
-        
-        <div class="card">
-            <div style="color: #666; font-size: 0.8rem; margin-bottom: 10px;">YOUR API KEY</div>
-            <div class="key-box">sk_live_883a9f_demo_key_x99</div>
-            <div style="color: #444; font-size: 0.8rem; margin-top: 10px;">Use this key to authenticate your requests. Limit: 5 req/sec.</div>
-        </div>
-
-        <div style="display: grid; grid-template-columns: 1fr 1fr; gap: 20px;">
-            <div class="card">
-                <div style="color: #666; font-size: 0.8rem;">REQUESTS TODAY</div>
-                <div style="font-size: 2rem; margin-top: 10px;">1,024 <span style="font-size:1rem; color:#444;">/ 10,000</span></div>
-            </div>
-            <div class="card">
-                <div style="color: #666; font-size: 0.8rem;">SERVICE STATUS</div>
-                <div style="font-size: 2rem; margin-top: 10px; color: #00FF9D;">● Optimal</div>
-            </div>
-        </div>
+    <div class="grid">
+        <div class="card"><div style="color:#666;font-size:0.7rem">STATUS</div><div style="font-size:1.5rem;color:#00ff9d" id="d-status">● --</div></div>
+        <div class="card"><div style="color:#666;font-size:0.7rem">LATENCY</div><div style="font-size:1.5rem" id="d-lat">-- ms</div></div>
+        <div class="card"><div style="color:#666;font-size:0.7rem">PLAN</div><div style="font-size:1.5rem">Enterprise</div></div>
     </div>
-</body>
-</html>
-    "##)
+</div>
+<script>
+    const p = new URLSearchParams(window.location.search);
+    const u = p.get('u');
+    if(!u) window.location.href='/login';
+    document.getElementById('u-email').innerText = u;
+    // Key asli cuma ada di sesi browser ini (diset waktu register), server hanya simpan hash
+    const apiKey = sessionStorage.getItem('arkheion_key');
+    document.getElementById('apikey').innerText = apiKey ? apiKey.split('_').slice(0,3).join('_') + "_••••••••" : "Hidden. Keys are only shown once at creation.";
+
+    const ctx = document.getElementById('tpsChart').getContext('2d');
+    const chart = new Chart(ctx, {
+        type: 'line',
+        data: { labels: Array(20).fill(''), datasets: [{ label: 'TPS', data: Array(20).fill(0), borderColor: '#00ff9d', tension: 0.4, borderWidth: 2, pointRadius: 0 }] },
+        options: { responsive: true, maintainAspectRatio: false, plugins: { legend: { display: false } }, scales: { x: { display: false }, y: { grid: { color: '#222' } } } }
+    });
+
+    // Pakai stream publik supaya grafik tidak menghabiskan credit API key.
+    // EventSource reconnect sendiri dan mengirim Last-Event-ID, jadi titik yang terlewat diputar ulang.
+    const feed = new EventSource('/api/metrics/stream');
+    feed.addEventListener('metrics', (e) => {
+        const d = JSON.parse(e.data).data;
+
+        document.getElementById('d-lat').innerText = d.latency_ms + " ms";
+        const st = document.getElementById('d-status');
+        st.innerText = "● " + d.status.charAt(0) + d.status.slice(1).toLowerCase();
+        st.style.color = { OPERATIONAL: '#00ff9d', DEGRADED: '#ffb300', BOOTING: '#888' }[d.status] || '#f33';
+
+        // Grafik disimpan di sisi browser: geser kiri, null = TPS tidak tersedia
+        chart.data.datasets[0].data.shift();
+        chart.data.datasets[0].data.push(d.tps);
+        chart.update('none');
+    });
+</script></body></html>"##)
 }
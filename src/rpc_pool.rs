@@ -0,0 +1,502 @@
+use axum::{extract::State, response::{IntoResponse, Json}};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use futures::future::join_all;
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_response::RpcPerfSample;
+use solana_sdk::{epoch_info::EpochInfo, signature::Signature};
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, TransactionStatus};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+use crate::exporter;
+use crate::models::AppState;
+use crate::rpc_source::{BoxFuture, RpcSource, SolanaRpc};
+
+/// Calls remembered per endpoint for the error rate.
+const ERROR_WINDOW: usize = 20;
+/// Above either limit an endpoint is only used when nothing better is left.
+const MAX_ERROR_RATE: f64 = 0.5;
+pub const MAX_SLOT_LAG: u64 = 50;
+/// Blocks are compared this many slots below the slowest node's tip, deep enough that
+/// every node has them confirmed.
+const FORK_CHECK_DEPTH: u64 = 32;
+/// A healthy active endpoint is only replaced by one scoring at least this much better,
+/// so two similar providers don't flap.
+const FAILBACK_MARGIN: f64 = 0.8;
+// Weights turning lag and errors into "milliseconds" comparable with latency.
+const MS_PER_SLOT: f64 = 400.0;
+const MS_PER_ERROR_RATE: f64 = 10_000.0;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EndpointHealth {
+    /// Exponentially weighted latency of successful calls.
+    pub latency_ms: Option<f64>,
+    pub slot: Option<u64>,
+    /// Slots behind the highest slot seen across the pool at the last probe.
+    pub slot_lag: u64,
+    pub error_rate: f64,
+    /// Reported a different block hash than the majority at the last fork check.
+    pub forked: bool,
+    pub last_error: Option<String>,
+    pub last_success: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    outcomes: VecDeque<bool>,
+}
+
+impl EndpointHealth {
+    fn record(&mut self, outcome: Result<f64, String>) {
+        if self.outcomes.len() == ERROR_WINDOW {
+            self.outcomes.pop_front();
+        }
+        self.outcomes.push_back(outcome.is_ok());
+        let errors = self.outcomes.iter().filter(|ok| !**ok).count();
+        self.error_rate = errors as f64 / self.outcomes.len() as f64;
+
+        match outcome {
+            Ok(ms) => {
+                self.latency_ms = Some(self.latency_ms.map_or(ms, |avg| avg * 0.7 + ms * 0.3));
+                self.last_success = Some(Utc::now());
+            }
+            Err(e) => self.last_error = Some(e),
+        }
+    }
+
+    pub fn healthy(&self) -> bool {
+        self.last_success.is_some()
+            && self.error_rate <= MAX_ERROR_RATE
+            && self.slot_lag <= MAX_SLOT_LAG
+            && !self.forked
+    }
+
+    /// Lower is better. Endpoints that never answered sort last.
+    pub fn score(&self) -> f64 {
+        match self.latency_ms {
+            Some(ms) => ms + self.slot_lag as f64 * MS_PER_SLOT + self.error_rate * MS_PER_ERROR_RATE,
+            None => f64::INFINITY,
+        }
+    }
+}
+
+pub struct Endpoint {
+    pub url: String,
+    pub source: Arc<dyn RpcSource>,
+    timeout: Duration,
+    health: Mutex<EndpointHealth>,
+}
+
+impl Endpoint {
+    pub fn health(&self) -> EndpointHealth {
+        self.health.lock().unwrap().clone()
+    }
+
+    /// Runs one RPC request in an `rpc` span, bounded by the pool's call timeout, and
+    /// records its duration and outcome for `/metrics`. Does not touch `health`; callers
+    /// decide what the result means.
+    async fn timed<T, F, Fut>(&self, method: &'static str, f: F) -> (Result<T, ClientError>, Duration)
+    where
+        F: FnOnce(Arc<dyn RpcSource>) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let endpoint = redact(&self.url);
+        let span = tracing::debug_span!("rpc", method, endpoint = %endpoint);
+
+        let start = Instant::now();
+        // The client's own HTTP timeout does not cover its 429 retries; this is the hard cap.
+        let result = match tokio::time::timeout(self.timeout, f(self.source.clone())).instrument(span.clone()).await {
+            Ok(result) => result,
+            Err(_) => Err(ClientErrorKind::Custom(format!("{} timed out after {:?}", method, self.timeout)).into()),
+        };
+        let elapsed = start.elapsed();
+        exporter::registry().rpc_call(method, &endpoint, elapsed, result.is_ok());
+        span.in_scope(|| match &result {
+            Ok(_) => tracing::debug!(elapsed_ms = elapsed.as_millis() as u64, "ok"),
+            Err(e) => tracing::debug!(elapsed_ms = elapsed.as_millis() as u64, error = %e, "failed"),
+        });
+        (result, elapsed)
+    }
+}
+
+/// What the last probe saw across the pool. Endpoints are identified by redacted URL.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProbeReport {
+    /// Highest slot any endpoint reported.
+    pub tip: Option<u64>,
+    /// Lag of the endpoint that was active after the probe.
+    pub active_lag: u64,
+    /// More than the probe's `max_lag` slots behind `tip`.
+    pub lagging: Vec<String>,
+    /// Disagreed with the majority on the block hash at `fork_slot`.
+    pub forked: Vec<String>,
+    pub fork_slot: Option<u64>,
+    /// Hashes at `fork_slot` differed but no hash had a strict majority, so nobody is
+    /// flagged as forked.
+    pub no_consensus: bool,
+}
+
+/// A set of interchangeable RPC providers. Calls go to the active endpoint and fail over
+/// to the next best on error; `probe` re-scores everything and fails back when the
+/// original recovers.
+pub struct RpcPool {
+    endpoints: Vec<Endpoint>,
+    active: AtomicUsize,
+}
+
+impl RpcPool {
+    /// `timeout` bounds every single request, so one hung provider costs at most that
+    /// long before the pool moves on.
+    pub fn new(urls: &[String], timeout: Duration) -> Self {
+        let sources = urls.iter().map(|url| Arc::new(SolanaRpc::new(url, timeout)) as Arc<dyn RpcSource>).collect();
+        Self::from_sources(sources, timeout)
+    }
+
+    pub fn from_sources(sources: Vec<Arc<dyn RpcSource>>, timeout: Duration) -> Self {
+        assert!(!sources.is_empty(), "RpcPool needs at least one endpoint");
+        let endpoints = sources.into_iter()
+            .map(|source| Endpoint {
+                url: source.url().to_string(),
+                source,
+                timeout,
+                health: Mutex::new(EndpointHealth::default()),
+            })
+            .collect();
+        Self { endpoints, active: AtomicUsize::new(0) }
+    }
+
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    pub fn active(&self) -> &Endpoint {
+        &self.endpoints[self.active.load(Ordering::Relaxed)]
+    }
+
+    /// Runs `f` against the active endpoint, then the remaining ones best-first, and
+    /// returns the first success. The endpoint that answered becomes active.
+    /// `method` is the JSON-RPC method name, used as a metrics label.
+    pub async fn call<T, F, Fut>(&self, method: &'static str, f: F) -> Result<T, ClientError>
+    where
+        F: Fn(Arc<dyn RpcSource>) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let mut last_err = None;
+
+        for i in self.candidates() {
+            let endpoint = &self.endpoints[i];
+            let (result, elapsed) = endpoint.timed(method, &f).await;
+            match result {
+                Ok(value) => {
+                    endpoint.health.lock().unwrap().record(Ok(elapsed.as_secs_f64() * 1000.0));
+                    self.switch_to(i);
+                    return Ok(value);
+                }
+                Err(e) => {
+                    endpoint.health.lock().unwrap().record(Err(e.to_string()));
+                    tracing::warn!(method, endpoint = %redact(&endpoint.url), error = %e, "rpc call failed");
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        Err(last_err.expect("pool has at least one endpoint"))
+    }
+
+    /// Active endpoint first, then healthy ones by score, then the rest by score.
+    fn candidates(&self) -> Vec<usize> {
+        let active = self.active.load(Ordering::Relaxed);
+        let health: Vec<EndpointHealth> = self.endpoints.iter().map(Endpoint::health).collect();
+
+        let mut order: Vec<usize> = (0..self.endpoints.len()).filter(|i| *i != active).collect();
+        order.sort_by(|a, b| {
+            let (ha, hb) = (&health[*a], &health[*b]);
+            hb.healthy().cmp(&ha.healthy()).then(ha.score().total_cmp(&hb.score()))
+        });
+        order.insert(0, active);
+        order
+    }
+
+    fn switch_to(&self, i: usize) {
+        let previous = self.active.swap(i, Ordering::Relaxed);
+        if previous != i {
+            exporter::registry().reconnect("failover");
+            tracing::warn!(
+                from = %redact(&self.endpoints[previous].url),
+                to = %redact(&self.endpoints[i].url),
+                "rpc failover"
+            );
+        }
+    }
+
+    /// Asks every endpoint for its slot, updates latency, lag and error rate, compares
+    /// block hashes to find forked nodes, and moves traffic to the best endpoint if the
+    /// active one is unhealthy or clearly worse. Endpoints are asked concurrently.
+    /// Endpoints more than `max_lag` slots behind the tip are reported as lagging.
+    pub async fn probe(&self, max_lag: u64) -> ProbeReport {
+        let answers = join_all(self.endpoints.iter().map(|endpoint| endpoint.timed("getSlot", |c| async move { c.get_slot().await }))).await;
+        let slots: Vec<Option<u64>> = self.endpoints.iter().zip(answers)
+            .map(|(endpoint, (slot, elapsed))| {
+                let mut health = endpoint.health.lock().unwrap();
+                match slot {
+                    Ok(slot) => {
+                        health.record(Ok(elapsed.as_secs_f64() * 1000.0));
+                        health.slot = Some(slot);
+                        Some(slot)
+                    }
+                    Err(e) => {
+                        health.record(Err(e.to_string()));
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        let tip = slots.iter().flatten().copied().max();
+        let (fork_slot, forked) = self.check_forks(&slots).await;
+        let no_consensus = fork_slot.is_some() && forked.is_none();
+        let forked = forked.unwrap_or_default();
+        for (i, (endpoint, slot)) in self.endpoints.iter().zip(&slots).enumerate() {
+            let mut health = endpoint.health.lock().unwrap();
+            // An endpoint that did not answer keeps its last known lag and fork state.
+            if let (Some(tip), Some(slot)) = (tip, slot) {
+                health.slot_lag = tip.saturating_sub(*slot);
+            }
+            if fork_slot.is_some() && slot.is_some() {
+                health.forked = forked.contains(&i);
+            }
+        }
+
+        let active = self.active.load(Ordering::Relaxed);
+        let current = self.endpoints[active].health();
+        let best = self.candidates().into_iter().nth(1).map(|i| (i, self.endpoints[i].health()));
+        if let Some((i, candidate)) = best {
+            let better = candidate.healthy() && candidate.score() < current.score() * FAILBACK_MARGIN;
+            if better || (!current.healthy() && candidate.healthy()) {
+                self.switch_to(i);
+            }
+        }
+
+        let health: Vec<EndpointHealth> = self.endpoints.iter().map(Endpoint::health).collect();
+        let named = |pred: &dyn Fn(&EndpointHealth) -> bool| -> Vec<String> {
+            self.endpoints.iter().zip(&health).filter(|(_, h)| pred(h)).map(|(e, _)| redact(&e.url)).collect()
+        };
+        ProbeReport {
+            tip,
+            active_lag: self.active().health().slot_lag,
+            lagging: named(&|h| h.slot_lag > max_lag),
+            forked: named(&|h| h.forked),
+            fork_slot,
+            no_consensus,
+        }
+    }
+
+    /// Fetches the same confirmed block from every endpoint that answered the slot probe
+    /// and returns the slot compared plus the indexes that disagree with the majority hash,
+    /// or `None` for them when no hash has a strict majority. Needs at least two endpoints;
+    /// endpoints that can't serve the block are left out.
+    async fn check_forks(&self, slots: &[Option<u64>]) -> (Option<u64>, Option<Vec<usize>>) {
+        let answering: Vec<usize> = (0..slots.len()).filter(|i| slots[*i].is_some()).collect();
+        let Some(lowest) = slots.iter().flatten().copied().min() else {
+            return (None, Some(Vec::new()));
+        };
+        if answering.len() < 2 {
+            return (None, Some(Vec::new()));
+        }
+
+        // The slot itself may have been skipped; compare the first block produced after it.
+        // Asked of one endpoint that just answered rather than through `call`, so the health
+        // check cannot fail over and change routing by itself.
+        let active = self.active.load(Ordering::Relaxed);
+        let asked = if slots[active].is_some() { active } else { answering[0] };
+        let from = lowest.saturating_sub(FORK_CHECK_DEPTH);
+        let (blocks, _) = self.endpoints[asked]
+            .timed("getBlocksWithLimit", |c| async move { c.get_blocks_with_limit(from, 1).await })
+            .await;
+        let Some(reference) = blocks.ok().and_then(|blocks| blocks.first().copied()) else {
+            return (None, Some(Vec::new()));
+        };
+
+        let blocks = join_all(answering.iter().map(|i| {
+            self.endpoints[*i].timed("getBlock", |c| async move { c.get_block_hash(reference).await })
+        }))
+        .await;
+        let hashes: Vec<(usize, String)> = answering.into_iter().zip(blocks)
+            .filter_map(|(i, (hash, _))| hash.ok().map(|hash| (i, hash)))
+            .collect();
+        if hashes.len() < 2 {
+            return (None, Some(Vec::new()));
+        }
+
+        let mut votes: HashMap<&str, usize> = HashMap::new();
+        for (_, hash) in &hashes {
+            *votes.entry(hash.as_str()).or_default() += 1;
+        }
+        // Without a strict majority (two nodes disagreeing, any tie) there is no telling
+        // which side forked; picking one would flag an arbitrary node on every probe.
+        let Some(majority) = votes.into_iter().find(|(_, n)| n * 2 > hashes.len()).map(|(hash, _)| hash.to_string()) else {
+            tracing::warn!(slot = reference, "rpc endpoints disagree on block hash with no majority");
+            return (Some(reference), None);
+        };
+
+        let forked = hashes.iter()
+            .filter(|(_, hash)| *hash != majority)
+            .map(|(i, _)| *i)
+            .collect();
+        (Some(reference), Some(forked))
+    }
+}
+
+/// The pool is itself a source, so callers outside the engine (payment checks) get the
+/// same failover. `url` is whichever endpoint is active.
+impl RpcSource for RpcPool {
+    fn url(&self) -> &str {
+        &self.active().url
+    }
+
+    fn get_slot(&self) -> BoxFuture<'_, ClientResult<u64>> {
+        Box::pin(self.call("getSlot", |c| async move { c.get_slot().await }))
+    }
+
+    fn get_epoch_info(&self) -> BoxFuture<'_, ClientResult<EpochInfo>> {
+        Box::pin(self.call("getEpochInfo", |c| async move { c.get_epoch_info().await }))
+    }
+
+    fn get_recent_performance_samples(&self, limit: usize) -> BoxFuture<'_, ClientResult<Vec<RpcPerfSample>>> {
+        Box::pin(self.call("getRecentPerformanceSamples", move |c| async move {
+            c.get_recent_performance_samples(limit).await
+        }))
+    }
+
+    fn get_blocks_with_limit(&self, start_slot: u64, limit: usize) -> BoxFuture<'_, ClientResult<Vec<u64>>> {
+        Box::pin(self.call("getBlocksWithLimit", move |c| async move {
+            c.get_blocks_with_limit(start_slot, limit).await
+        }))
+    }
+
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> BoxFuture<'_, ClientResult<Vec<u64>>> {
+        Box::pin(self.call("getBlocks", move |c| async move { c.get_blocks(start_slot, end_slot).await }))
+    }
+
+    fn get_block_hash(&self, slot: u64) -> BoxFuture<'_, ClientResult<String>> {
+        Box::pin(self.call("getBlock", move |c| async move { c.get_block_hash(slot).await }))
+    }
+
+    fn get_signature_status(&self, signature: Signature) -> BoxFuture<'_, ClientResult<Option<TransactionStatus>>> {
+        Box::pin(self.call("getSignatureStatuses", move |c| async move { c.get_signature_status(signature).await }))
+    }
+
+    fn get_transaction(&self, signature: Signature) -> BoxFuture<'_, ClientResult<EncodedConfirmedTransactionWithStatusMeta>> {
+        Box::pin(self.call("getTransaction", move |c| async move { c.get_transaction(signature).await }))
+    }
+}
+
+/// Provider URLs often carry an API key in the path or query; only scheme and host are shown.
+pub fn redact(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let host = rest.split(['/', '?']).next().unwrap_or(rest);
+    if scheme.is_empty() { host.to_string() } else { format!("{}://{}", scheme, host) }
+}
+
+/// Per-endpoint health as seen by the engine, active endpoint first.
+pub async fn rpc_health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let active = state.rpc_pool.active().url.clone();
+    let endpoints: Vec<_> = state.rpc_pool.endpoints().iter()
+        .map(|endpoint| {
+            let health = endpoint.health();
+            json!({
+                "url": redact(&endpoint.url),
+                "active": endpoint.url == active,
+                "healthy": health.healthy(),
+                "score": health.score().is_finite().then(|| health.score().round()),
+                "health": health
+            })
+        })
+        .collect();
+
+    Json(json!({ "active": redact(&active), "endpoints": endpoints }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_source::mock::ScriptedRpc;
+    use serde_json::json;
+
+    fn pool(urls: &[&str]) -> (RpcPool, Vec<Arc<ScriptedRpc>>) {
+        let mocks: Vec<Arc<ScriptedRpc>> = urls.iter().map(|u| Arc::new(ScriptedRpc::new(u))).collect();
+        let sources = mocks.iter().map(|m| m.clone() as Arc<dyn RpcSource>).collect();
+        (RpcPool::from_sources(sources, Duration::from_secs(1)), mocks)
+    }
+
+    #[tokio::test]
+    async fn call_fails_over_and_sticks() {
+        let (pool, mocks) = pool(&["http://primary", "http://backup"]);
+        mocks[0].push_error("getSlot", "503 Service Unavailable");
+        mocks[1].push("getSlot", json!(42));
+
+        let slot = pool.call("getSlot", |c| async move { c.get_slot().await }).await.unwrap();
+        assert_eq!(slot, 42);
+        assert_eq!(pool.active().url, "http://backup");
+        assert!(pool.endpoints()[0].health().last_error.is_some_and(|e| e.contains("503")));
+
+        mocks[1].clear("getSlot").push_error("getSlot", "down too");
+        assert!(pool.call("getSlot", |c| async move { c.get_slot().await }).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn pool_as_a_source_fails_over() {
+        let (pool, mocks) = pool(&["http://primary", "http://backup"]);
+        mocks[0].push_error("getBlocks", "503 Service Unavailable");
+        mocks[1].push("getBlocks", json!([10, 12]));
+
+        let source: &dyn RpcSource = &pool;
+        assert_eq!(source.url(), "http://primary");
+        assert_eq!(source.get_blocks(10, 12).await.unwrap(), vec![10, 12]);
+        assert_eq!(source.url(), "http://backup");
+    }
+
+    #[tokio::test]
+    async fn probe_finds_lagging_and_forked_nodes() {
+        let (pool, mocks) = pool(&["http://a", "http://b", "http://c"]);
+        for (mock, (slot, hash)) in mocks.iter().zip([(1_000, "HashA"), (1_000, "HashA"), (900, "HashB")]) {
+            mock.push("getSlot", json!(slot)).push("getBlock", json!({ "blockhash": hash }));
+        }
+        mocks[0].push("getBlocksWithLimit", json!([868]));
+
+        let report = pool.probe(MAX_SLOT_LAG).await;
+        assert_eq!(report.tip, Some(1_000));
+        assert_eq!(report.lagging, vec!["http://c".to_string()]);
+        assert_eq!(report.forked, vec!["http://c".to_string()]);
+        assert_eq!(report.fork_slot, Some(868));
+        assert_eq!(pool.endpoints()[2].health().slot_lag, 100);
+        assert!(!report.no_consensus);
+    }
+
+    #[tokio::test]
+    async fn two_disagreeing_endpoints_flag_nobody() {
+        let (pool, mocks) = pool(&["http://a", "http://b"]);
+        for (mock, hash) in mocks.iter().zip(["HashA", "HashB"]) {
+            mock.push("getSlot", json!(1_000)).push("getBlock", json!({ "blockhash": hash }));
+        }
+        mocks[0].push("getBlocksWithLimit", json!([968]));
+
+        let report = pool.probe(MAX_SLOT_LAG).await;
+        assert_eq!(report.fork_slot, Some(968));
+        assert!(report.no_consensus);
+        assert!(report.forked.is_empty());
+        assert!(pool.endpoints().iter().all(|e| !e.health().forked));
+    }
+
+    #[tokio::test]
+    async fn lag_threshold_comes_from_the_caller() {
+        let (pool, mocks) = pool(&["http://a", "http://b"]);
+        mocks[0].push("getSlot", json!(1_000));
+        mocks[1].push("getSlot", json!(980));
+
+        assert!(pool.probe(MAX_SLOT_LAG).await.lagging.is_empty());
+        assert_eq!(pool.probe(10).await.lagging, vec!["http://b".to_string()]);
+    }
+}
@@ -0,0 +1,236 @@
+use solana_client::client_error::Result as ClientResult;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcBlockConfig, RpcTransactionConfig};
+use solana_client::rpc_response::RpcPerfSample;
+use solana_sdk::{commitment_config::CommitmentConfig, epoch_info::EpochInfo, signature::Signature};
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, TransactionDetails, TransactionStatus, UiTransactionEncoding,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The Solana JSON-RPC calls the engine, the pool and payment verification make. The
+/// server talks to [`SolanaRpc`]; tests script answers with `mock::ScriptedRpc`.
+pub trait RpcSource: Send + Sync {
+    /// Where requests go. Only ever shown redacted.
+    fn url(&self) -> &str;
+
+    fn get_slot(&self) -> BoxFuture<'_, ClientResult<u64>>;
+
+    fn get_epoch_info(&self) -> BoxFuture<'_, ClientResult<EpochInfo>>;
+
+    fn get_recent_performance_samples(&self, limit: usize) -> BoxFuture<'_, ClientResult<Vec<RpcPerfSample>>>;
+
+    fn get_blocks_with_limit(&self, start_slot: u64, limit: usize) -> BoxFuture<'_, ClientResult<Vec<u64>>>;
+
+    /// Confirmed blocks in `start_slot..=end_slot`; the slots missing from it were skipped.
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> BoxFuture<'_, ClientResult<Vec<u64>>>;
+
+    /// Hash of the confirmed block at `slot`.
+    fn get_block_hash(&self, slot: u64) -> BoxFuture<'_, ClientResult<String>>;
+
+    /// `None` when the node has never seen the signature.
+    fn get_signature_status(&self, signature: Signature) -> BoxFuture<'_, ClientResult<Option<TransactionStatus>>>;
+
+    /// The finalized transaction, `jsonParsed`.
+    fn get_transaction(&self, signature: Signature) -> BoxFuture<'_, ClientResult<EncodedConfirmedTransactionWithStatusMeta>>;
+}
+
+/// A real provider over HTTP, at confirmed commitment unless a call says otherwise.
+pub struct SolanaRpc {
+    url: String,
+    client: RpcClient,
+}
+
+impl SolanaRpc {
+    pub fn new(url: &str, timeout: Duration) -> Self {
+        let client = RpcClient::new_with_timeout_and_commitment(url.to_string(), timeout, CommitmentConfig::confirmed());
+        Self { url: url.to_string(), client }
+    }
+}
+
+impl RpcSource for SolanaRpc {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn get_slot(&self) -> BoxFuture<'_, ClientResult<u64>> {
+        Box::pin(self.client.get_slot())
+    }
+
+    fn get_epoch_info(&self) -> BoxFuture<'_, ClientResult<EpochInfo>> {
+        Box::pin(self.client.get_epoch_info())
+    }
+
+    fn get_recent_performance_samples(&self, limit: usize) -> BoxFuture<'_, ClientResult<Vec<RpcPerfSample>>> {
+        Box::pin(self.client.get_recent_performance_samples(Some(limit)))
+    }
+
+    fn get_blocks_with_limit(&self, start_slot: u64, limit: usize) -> BoxFuture<'_, ClientResult<Vec<u64>>> {
+        Box::pin(self.client.get_blocks_with_limit(start_slot, limit))
+    }
+
+    fn get_blocks(&self, start_slot: u64, end_slot: u64) -> BoxFuture<'_, ClientResult<Vec<u64>>> {
+        Box::pin(self.client.get_blocks(start_slot, Some(end_slot)))
+    }
+
+    fn get_block_hash(&self, slot: u64) -> BoxFuture<'_, ClientResult<String>> {
+        let config = RpcBlockConfig {
+            transaction_details: Some(TransactionDetails::None),
+            rewards: Some(false),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+            ..RpcBlockConfig::default()
+        };
+        Box::pin(async move { Ok(self.client.get_block_with_config(slot, config).await?.blockhash) })
+    }
+
+    fn get_signature_status(&self, signature: Signature) -> BoxFuture<'_, ClientResult<Option<TransactionStatus>>> {
+        Box::pin(async move {
+            let res = self.client.get_signature_statuses(&[signature]).await?;
+            Ok(res.value.into_iter().next().flatten())
+        })
+    }
+
+    fn get_transaction(&self, signature: Signature) -> BoxFuture<'_, ClientResult<EncodedConfirmedTransactionWithStatusMeta>> {
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::JsonParsed),
+            commitment: Some(CommitmentConfig::finalized()),
+            max_supported_transaction_version: Some(0),
+        };
+        Box::pin(async move { self.client.get_transaction_with_config(&signature, config).await })
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use serde::{de::DeserializeOwned, Deserialize};
+    use serde_json::Value;
+    use solana_client::client_error::{ClientError, ClientErrorKind};
+    use solana_client::rpc_response::Response;
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::Mutex;
+
+    /// Offline [`RpcSource`]. Every method answers from its own queue of scripted
+    /// JSON-RPC results; the last one keeps repeating once the queue is down to it, and a
+    /// method with nothing scripted fails. Results are the raw `result` JSON, so recorded
+    /// provider responses can be loaded as they are (see [`ScriptedRpc::load_fixture`]).
+    pub struct ScriptedRpc {
+        url: String,
+        replies: Mutex<HashMap<String, VecDeque<Result<Value, String>>>>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl ScriptedRpc {
+        pub fn new(url: &str) -> Self {
+            Self { url: url.to_string(), replies: Mutex::default(), calls: Mutex::default() }
+        }
+
+        pub fn push(&self, method: &str, result: Value) -> &Self {
+            self.enqueue(method, Ok(result))
+        }
+
+        pub fn push_error(&self, method: &str, message: &str) -> &Self {
+            self.enqueue(method, Err(message.to_string()))
+        }
+
+        /// Drops whatever is still queued for `method`.
+        pub fn clear(&self, method: &str) -> &Self {
+            self.replies.lock().unwrap().remove(method);
+            self
+        }
+
+        /// Queues recorded exchanges: a JSON array of
+        /// `{"method": "...", "response": {"jsonrpc": "2.0", "result": ..., "id": 1}}`,
+        /// where a response with `error` instead of `result` replays as a failure.
+        /// Recordings live in `tests/fixtures/rpc/`.
+        pub fn load_fixture(&self, json: &str) -> &Self {
+            let exchanges: Vec<Value> = serde_json::from_str(json).expect("fixture is not a JSON array");
+            for exchange in exchanges {
+                let method = exchange["method"].as_str().expect("fixture entry without method");
+                let response = &exchange["response"];
+                match response.get("error") {
+                    Some(error) => self.enqueue(method, Err(error["message"].as_str().unwrap_or("error").to_string())),
+                    None => self.enqueue(method, Ok(response["result"].clone())),
+                };
+            }
+            self
+        }
+
+        /// Methods called so far, in order.
+        pub fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        fn enqueue(&self, method: &str, reply: Result<Value, String>) -> &Self {
+            self.replies.lock().unwrap().entry(method.to_string()).or_default().push_back(reply);
+            self
+        }
+
+        fn reply<T: DeserializeOwned + Send + 'static>(&self, method: &str) -> BoxFuture<'_, ClientResult<T>> {
+            self.calls.lock().unwrap().push(method.to_string());
+            let reply = {
+                let mut replies = self.replies.lock().unwrap();
+                match replies.get_mut(method) {
+                    Some(queue) if queue.len() > 1 => queue.pop_front(),
+                    Some(queue) => queue.front().cloned(),
+                    None => None,
+                }
+            };
+            let result = match reply {
+                Some(Ok(value)) => serde_json::from_value(value).map_err(ClientError::from),
+                Some(Err(message)) => Err(ClientErrorKind::Custom(message).into()),
+                None => Err(ClientErrorKind::Custom(format!("{} not scripted", method)).into()),
+            };
+            Box::pin(async move { result })
+        }
+    }
+
+    impl RpcSource for ScriptedRpc {
+        fn url(&self) -> &str {
+            &self.url
+        }
+
+        fn get_slot(&self) -> BoxFuture<'_, ClientResult<u64>> {
+            self.reply("getSlot")
+        }
+
+        fn get_epoch_info(&self) -> BoxFuture<'_, ClientResult<EpochInfo>> {
+            self.reply("getEpochInfo")
+        }
+
+        fn get_recent_performance_samples(&self, _limit: usize) -> BoxFuture<'_, ClientResult<Vec<RpcPerfSample>>> {
+            self.reply("getRecentPerformanceSamples")
+        }
+
+        fn get_blocks_with_limit(&self, _start_slot: u64, _limit: usize) -> BoxFuture<'_, ClientResult<Vec<u64>>> {
+            self.reply("getBlocksWithLimit")
+        }
+
+        fn get_blocks(&self, _start_slot: u64, _end_slot: u64) -> BoxFuture<'_, ClientResult<Vec<u64>>> {
+            self.reply("getBlocks")
+        }
+
+        fn get_block_hash(&self, _slot: u64) -> BoxFuture<'_, ClientResult<String>> {
+            #[derive(Deserialize)]
+            struct Block {
+                blockhash: String,
+            }
+            let block = self.reply::<Block>("getBlock");
+            Box::pin(async move { Ok(block.await?.blockhash) })
+        }
+
+        fn get_signature_status(&self, _signature: Signature) -> BoxFuture<'_, ClientResult<Option<TransactionStatus>>> {
+            let statuses = self.reply::<Response<Vec<Option<TransactionStatus>>>>("getSignatureStatuses");
+            Box::pin(async move { Ok(statuses.await?.value.into_iter().next().flatten()) })
+        }
+
+        fn get_transaction(&self, _signature: Signature) -> BoxFuture<'_, ClientResult<EncodedConfirmedTransactionWithStatusMeta>> {
+            self.reply("getTransaction")
+        }
+    }
+}
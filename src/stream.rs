@@ -0,0 +1,279 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    Extension,
+};
+use futures::stream::{self, Stream, StreamExt};
+use serde_json::json;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::watch;
+use crate::engine::{Alert, EngineMetrics, StatusTransition};
+use crate::keys::AuthedKey;
+use crate::models::AppState;
+use crate::routes;
+
+/// Events kept for `Last-Event-ID` resume. Slot notifications publish ~2.5 events/s, so
+/// the window is bounded by age, with a hard cap in case publishing speeds up.
+const HISTORY_WINDOW: Duration = Duration::from_secs(300);
+const HISTORY_MAX: usize = 2_048;
+/// Status transitions kept for `/api/v1/status`.
+const TRANSITION_HISTORY_LEN: usize = 50;
+/// Alerts buffered per subscriber before the slowest ones start missing some.
+const ALERT_BUFFER: usize = 64;
+const HEARTBEAT: Duration = Duration::from_secs(15);
+pub const MAX_STREAMS_PER_KEY: usize = 5;
+pub const MAX_STREAMS_PER_IP: usize = 2;
+
+#[derive(Debug, Clone)]
+pub struct MetricsEvent {
+    pub id: u64,
+    pub metrics: EngineMetrics,
+}
+
+/// Fan-out point between the engine tasks and every reader. Readers either take the
+/// latest snapshot or wait for the next change.
+pub struct MetricsHub {
+    tx: watch::Sender<MetricsEvent>,
+    /// Published events with when they were published, oldest first.
+    history: Mutex<VecDeque<(Instant, MetricsEvent)>>,
+    transitions: Mutex<VecDeque<StatusTransition>>,
+    alerts: broadcast::Sender<Alert>,
+}
+
+impl Default for MetricsHub {
+    fn default() -> Self {
+        let (tx, _) = watch::channel(MetricsEvent { id: 0, metrics: EngineMetrics::default() });
+        let (alerts, _) = broadcast::channel(ALERT_BUFFER);
+        Self {
+            tx,
+            history: Mutex::new(VecDeque::new()),
+            transitions: Mutex::new(VecDeque::with_capacity(TRANSITION_HISTORY_LEN)),
+            alerts,
+        }
+    }
+}
+
+impl MetricsHub {
+    pub fn latest(&self) -> EngineMetrics {
+        self.tx.borrow().metrics.clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<MetricsEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Alerts are fire-and-forget: not replayed on resume and dropped when nobody listens.
+    pub fn alert(&self, alert: Alert) {
+        let _ = self.alerts.send(alert);
+    }
+
+    pub fn subscribe_alerts(&self) -> broadcast::Receiver<Alert> {
+        self.alerts.subscribe()
+    }
+
+    /// Recent status transitions, oldest first.
+    pub fn transitions(&self) -> Vec<StatusTransition> {
+        self.transitions.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Edits the current snapshot in place (the engine's polling loop and slot subscription
+    /// each own some of the fields), then assigns the next event id and wakes subscribers,
+    /// unless nothing changed. A changed status is also logged as a transition.
+    pub fn update(&self, edit: impl FnOnce(&mut EngineMetrics)) {
+        let mut history = self.history.lock().unwrap();
+        let mut transition = None;
+        let published = self.tx.send_if_modified(|current| {
+            let mut metrics = current.metrics.clone();
+            edit(&mut metrics);
+            if current.metrics == metrics {
+                return false;
+            }
+            transition = metrics.transition_from(&current.metrics);
+            *current = MetricsEvent { id: current.id + 1, metrics };
+            true
+        });
+        if !published {
+            return;
+        }
+
+        if let Some(t) = transition {
+            tracing::info!(from = t.from.as_str(), to = t.to.as_str(), reason = ?t.reason, "{}", t.detail);
+            let mut transitions = self.transitions.lock().unwrap();
+            if transitions.len() == TRANSITION_HISTORY_LEN {
+                transitions.pop_front();
+            }
+            transitions.push_back(t);
+        }
+
+        let now = Instant::now();
+        while history.len() >= HISTORY_MAX
+            || history.front().is_some_and(|(at, _)| now.duration_since(*at) > HISTORY_WINDOW)
+        {
+            history.pop_front();
+        }
+        history.push_back((now, self.tx.borrow().clone()));
+    }
+
+    /// Events after `last_id`, or `None` when the gap is no longer (or never was) in history.
+    pub fn since(&self, last_id: u64) -> Option<Vec<MetricsEvent>> {
+        let history = self.history.lock().unwrap();
+        let oldest = history.front()?.1.id;
+        let newest = history.back()?.1.id;
+        if last_id.saturating_add(1) < oldest || last_id > newest {
+            return None;
+        }
+        Some(history.iter().map(|(_, e)| e).filter(|e| e.id > last_id).cloned().collect())
+    }
+}
+
+/// Counts open streams per caller. The returned guard releases the slot when the
+/// client disconnects and the response body is dropped.
+#[derive(Default)]
+pub struct StreamLimiter {
+    open: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+pub struct StreamGuard {
+    key: String,
+    open: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl StreamLimiter {
+    pub fn acquire(&self, key: String, limit: usize) -> Option<StreamGuard> {
+        let mut open = self.open.lock().unwrap();
+        let count = open.entry(key.clone()).or_insert(0);
+        if *count >= limit {
+            return None;
+        }
+        *count += 1;
+        Some(StreamGuard { key, open: self.open.clone() })
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        let mut open = self.open.lock().unwrap();
+        if let Some(count) = open.get_mut(&self.key) {
+            *count -= 1;
+            if *count == 0 {
+                open.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Live engine metrics over SSE for API key holders; auth and billing (one charge per
+/// connection) are done by `metering::meter`.
+pub async fn api_stream(
+    State(state): State<Arc<AppState>>,
+    Extension(key): Extension<AuthedKey>,
+    headers: HeaderMap,
+) -> Response {
+    open_stream(&state, key_caller(&key), MAX_STREAMS_PER_KEY, &headers)
+}
+
+/// Limiter key for a key holder. SSE and WebSocket connections share the same budget.
+pub fn key_caller(key: &AuthedKey) -> String {
+    match key.api_key_id {
+        Some(id) => format!("key:{}", id),
+        None => format!("user:{}", key.user_id),
+    }
+}
+
+pub fn too_many_streams(limit: usize) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(json!({ "error": "Too many open streams", "limit": limit })),
+    )
+        .into_response()
+}
+
+/// Unmetered feed for the landing page and dashboard, limited per IP.
+pub async fn public_stream(
+    State(state): State<Arc<AppState>>,
+    connect: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response {
+    let caller = match connect {
+        Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+        None => "ip:unknown".to_string(),
+    };
+    open_stream(&state, caller, MAX_STREAMS_PER_IP, &headers)
+}
+
+fn open_stream(state: &AppState, caller: String, limit: usize, headers: &HeaderMap) -> Response {
+    let Some(guard) = state.streams.acquire(caller, limit) else {
+        return too_many_streams(limit);
+    };
+
+    let last_id = headers.get("last-event-id")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+
+    Sse::new(metrics_events(&state.metrics, last_id, guard))
+        .keep_alive(KeepAlive::new().interval(HEARTBEAT).text("heartbeat"))
+        .into_response()
+}
+
+/// Replays what a resuming client missed (or the current snapshot if the gap is too
+/// old), then yields one `metrics` event per published change and an `alert` event
+/// (without an id, so resume position is unaffected) per alert.
+fn metrics_events(
+    hub: &MetricsHub,
+    last_id: Option<u64>,
+    guard: StreamGuard,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    let mut rx = hub.subscribe();
+    let alerts = hub.subscribe_alerts();
+    let current = rx.borrow_and_update().clone();
+
+    let backlog = match last_id {
+        Some(id) if id == current.id => Vec::new(),
+        // Anything newer than `current` will arrive through `rx`.
+        Some(id) => match hub.since(id) {
+            Some(missed) => missed.into_iter().filter(|e| e.id <= current.id).collect(),
+            None => vec![current],
+        },
+        None => vec![current],
+    };
+
+    let replay = stream::iter(backlog.into_iter().map(|e| Ok(to_event(&e))));
+    let live = stream::unfold((rx, alerts, guard), |(mut rx, mut alerts, guard)| async move {
+        let event = tokio::select! {
+            changed = rx.changed() => {
+                changed.ok()?;
+                let current = rx.borrow_and_update().clone();
+                to_event(&current)
+            }
+            alert = alerts.recv() => match alert {
+                Ok(alert) => Event::default()
+                    .event("alert")
+                    .json_data(&alert)
+                    .unwrap_or_else(|_| Event::default().comment("serialization error")),
+                Err(RecvError::Lagged(missed)) => Event::default().comment(format!("{} alerts dropped", missed)),
+                Err(RecvError::Closed) => return None,
+            },
+        };
+        Some((Ok(event), (rx, alerts, guard)))
+    });
+
+    replay.chain(live)
+}
+
+/// The body is what `/api/metrics` returns for the same snapshot.
+fn to_event(e: &MetricsEvent) -> Event {
+    Event::default()
+        .id(e.id.to_string())
+        .event("metrics")
+        .json_data(routes::envelope(routes::metrics_data(&e.metrics)))
+        .unwrap_or_else(|_| Event::default().comment("serialization error"))
+}
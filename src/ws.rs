@@ -0,0 +1,424 @@
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    Extension,
+};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval, interval_at, Duration, Instant};
+use crate::engine::EngineMetrics;
+use crate::keys::{self, AuthedKey, KeyError};
+use crate::metering;
+use crate::models::AppState;
+use crate::stream::{self, StreamGuard, MAX_STREAMS_PER_KEY};
+
+const PING_INTERVAL: Duration = Duration::from_secs(20);
+/// No frame at all (pong included) for this long and the client is considered gone.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Open sockets are billed `BILLING_COST` credits per interval, after the upfront charge
+/// taken by `metering::meter` on upgrade. The key is re-checked at the same time.
+const BILLING_INTERVAL: Duration = Duration::from_secs(60);
+const BILLING_COST: i64 = 1;
+
+// Application close codes (4000-4999 are reserved for private use by RFC 6455).
+const CLOSE_KEY_REJECTED: u16 = 4401;
+const CLOSE_CREDITS_EXHAUSTED: u16 = 4402;
+const CLOSE_IDLE: u16 = 4408;
+const CLOSE_INTERNAL: u16 = 1011;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Topic {
+    Slot,
+    Epoch,
+    Metrics,
+    Health,
+    Alerts,
+}
+
+impl Topic {
+    pub const ALL: [Topic; 5] = [Topic::Slot, Topic::Epoch, Topic::Metrics, Topic::Health, Topic::Alerts];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Topic::Slot => "slot",
+            Topic::Epoch => "epoch",
+            Topic::Metrics => "metrics",
+            Topic::Health => "health",
+            Topic::Alerts => "alerts",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|topic| topic.as_str() == s)
+    }
+
+    /// Payload for this topic, or `None` when it did not change since `prev`.
+    /// `alerts` is not derived from metrics and is pushed separately.
+    fn payload(&self, prev: Option<&EngineMetrics>, m: &EngineMetrics) -> Option<Value> {
+        match self {
+            Topic::Slot if prev.map_or(true, |p| p.slot != m.slot) => Some(json!({ "slot": m.slot })),
+            Topic::Epoch if prev.map_or(true, |p| p.epoch != m.epoch) => Some(json!({ "epoch": m.epoch })),
+            Topic::Metrics if prev != Some(m) => serde_json::to_value(m).ok(),
+            Topic::Health
+                if prev.map_or(true, |p| {
+                    (p.status, p.status_reason, p.consecutive_errors, p.latency)
+                        != (m.status, m.status_reason, m.consecutive_errors, m.latency)
+                }) =>
+            {
+                Some(json!({
+                    "status": m.status,
+                    "reason": m.status_reason,
+                    "consecutive_errors": m.consecutive_errors,
+                    "latency_ms": m.latency,
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Client → server frames, e.g. `{"op":"subscribe","topics":["slot","health"]}`.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { topics: Vec<String> },
+    Unsubscribe { topics: Vec<String> },
+}
+
+/// Server → client frames. `seq` increases by one per frame on a connection, so gaps
+/// mean the client dropped something.
+#[derive(Serialize)]
+struct ServerMessage<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    seq: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    topic: Option<&'a str>,
+    data: Value,
+}
+
+/// Upgrades to a WebSocket for key holders; auth and the upfront charge are done by
+/// `metering::meter`. Counts against the same per-key limit as `/api/v1/stream`.
+pub async fn ws_handler(
+    State(state): State<Arc<AppState>>,
+    Extension(key): Extension<AuthedKey>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let Some(guard) = state.streams.acquire(stream::key_caller(&key), MAX_STREAMS_PER_KEY) else {
+        return stream::too_many_streams(MAX_STREAMS_PER_KEY);
+    };
+    ws.on_upgrade(move |socket| run(socket, state, key, guard))
+}
+
+struct Connection {
+    seq: u64,
+    topics: BTreeSet<Topic>,
+}
+
+impl Connection {
+    fn frame(&mut self, kind: &str, topic: Option<Topic>, data: Value) -> Message {
+        self.seq += 1;
+        let msg = ServerMessage { kind, seq: self.seq, topic: topic.map(|t| t.as_str()), data };
+        Message::Text(serde_json::to_string(&msg).unwrap_or_default())
+    }
+
+    fn subscribed(&self) -> Vec<&'static str> {
+        self.topics.iter().map(|t| t.as_str()).collect()
+    }
+
+    /// Applies a client frame and returns the replies. New subscriptions get the current
+    /// value straight away so clients don't wait for the next change.
+    fn handle(&mut self, text: &str, current: &EngineMetrics) -> Vec<Message> {
+        let msg = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(msg) => msg,
+            Err(e) => return vec![self.frame("error", None, json!({ "message": e.to_string() }))],
+        };
+
+        let (subscribe, names) = match msg {
+            ClientMessage::Subscribe { topics } => (true, topics),
+            ClientMessage::Unsubscribe { topics } => (false, topics),
+        };
+
+        let mut topics = Vec::new();
+        for name in &names {
+            match Topic::parse(name) {
+                Some(topic) => topics.push(topic),
+                None => {
+                    let data = json!({ "message": format!("unknown topic '{}'", name) });
+                    return vec![self.frame("error", None, data)];
+                }
+            }
+        }
+
+        let mut replies = Vec::new();
+        let mut added = Vec::new();
+        for topic in topics {
+            if subscribe && self.topics.insert(topic) {
+                added.push(topic);
+            } else if !subscribe {
+                self.topics.remove(&topic);
+            }
+        }
+
+        let kind = if subscribe { "subscribed" } else { "unsubscribed" };
+        replies.push(self.frame(kind, None, json!({ "topics": self.subscribed() })));
+        for topic in added {
+            if let Some(data) = topic.payload(None, current) {
+                replies.push(self.frame("update", Some(topic), data));
+            }
+        }
+        replies
+    }
+
+    fn updates(&mut self, prev: &EngineMetrics, current: &EngineMetrics) -> Vec<Message> {
+        let topics: Vec<Topic> = self.topics.iter().copied().collect();
+        topics.into_iter()
+            .filter_map(|topic| topic.payload(Some(prev), current).map(|data| (topic, data)))
+            .map(|(topic, data)| self.frame("update", Some(topic), data))
+            .collect()
+    }
+}
+
+async fn run(socket: WebSocket, state: Arc<AppState>, key: AuthedKey, _guard: StreamGuard) {
+    let (mut sink, mut source) = socket.split();
+    let mut conn = Connection { seq: 0, topics: BTreeSet::new() };
+
+    let mut rx = state.metrics.subscribe();
+    let mut current = rx.borrow_and_update().metrics.clone();
+    let mut alerts = state.metrics.subscribe_alerts();
+
+    let mut ping = interval(PING_INTERVAL);
+    let mut billing = interval_at(Instant::now() + BILLING_INTERVAL, BILLING_INTERVAL);
+    let mut last_seen = Instant::now();
+
+    let (code, reason) = loop {
+        let outgoing = tokio::select! {
+            frame = source.next() => match frame {
+                Some(Ok(Message::Text(text))) => {
+                    last_seen = Instant::now();
+                    conn.handle(&text, &current)
+                }
+                Some(Ok(Message::Binary(_))) => {
+                    last_seen = Instant::now();
+                    vec![conn.frame("error", None, json!({ "message": "binary frames are not supported" }))]
+                }
+                // Pings are answered by the protocol layer; any frame proves liveness.
+                Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => {
+                    last_seen = Instant::now();
+                    Vec::new()
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+            },
+            changed = rx.changed() => {
+                if changed.is_err() {
+                    break (CLOSE_INTERNAL, "engine stopped");
+                }
+                let next = rx.borrow_and_update().metrics.clone();
+                let updates = conn.updates(&current, &next);
+                current = next;
+                updates
+            }
+            alert = alerts.recv() => match alert {
+                Ok(alert) if conn.topics.contains(&Topic::Alerts) => {
+                    let data = serde_json::to_value(&alert).unwrap_or_default();
+                    vec![conn.frame("update", Some(Topic::Alerts), data)]
+                }
+                Ok(_) => Vec::new(),
+                Err(RecvError::Lagged(missed)) if conn.topics.contains(&Topic::Alerts) => {
+                    vec![conn.frame("error", None, json!({ "message": format!("{} alerts dropped", missed) }))]
+                }
+                Err(RecvError::Lagged(_)) => Vec::new(),
+                Err(RecvError::Closed) => break (CLOSE_INTERNAL, "engine stopped"),
+            },
+            _ = ping.tick() => {
+                if last_seen.elapsed() >= IDLE_TIMEOUT {
+                    break (CLOSE_IDLE, "ping timeout");
+                }
+                vec![Message::Ping(Vec::new())]
+            }
+            _ = billing.tick() => {
+                match charge(&state, &key).await {
+                    Ok(()) => Vec::new(),
+                    Err(close) => break close,
+                }
+            }
+        };
+
+        for msg in outgoing {
+            if sink.send(msg).await.is_err() {
+                return;
+            }
+        }
+    };
+
+    let _ = sink.send(Message::Close(Some(CloseFrame { code, reason: reason.into() }))).await;
+}
+
+/// Periodic key re-check and debit. `Err` carries the close frame to send.
+async fn charge(state: &AppState, key: &AuthedKey) -> Result<(), (u16, &'static str)> {
+    match keys::recheck(&state.db, key).await {
+        Ok(()) => {}
+        Err(KeyError::Revoked) => return Err((CLOSE_KEY_REJECTED, "API key revoked")),
+        Err(KeyError::Expired) => return Err((CLOSE_KEY_REJECTED, "API key expired")),
+        Err(KeyError::Db(e)) => {
+            // A DB hiccup should not drop paying clients; try again next interval.
+            tracing::error!(error = %e, "ws key check failed");
+            return Ok(());
+        }
+        Err(_) => return Err((CLOSE_KEY_REJECTED, "API key invalid")),
+    }
+
+    match metering::debit(&state.db, key.user_id, BILLING_COST).await {
+        Ok(true) => {
+            if let Err(e) = metering::record_usage(&state.db, key, "/api/v1/ws", BILLING_COST, 101).await {
+                tracing::error!(error = %e, "usage ledger insert failed");
+            }
+            Ok(())
+        }
+        Ok(false) => Err((CLOSE_CREDITS_EXHAUSTED, "credits exhausted")),
+        Err(e) => {
+            tracing::error!(error = %e, "ws billing failed");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::db::memory_pool;
+    use crate::engine::EngineStatus;
+    use crate::rpc_source::mock::ScriptedRpc;
+    use chrono::{DateTime, Utc};
+
+    fn connection() -> Connection {
+        Connection { seq: 0, topics: BTreeSet::new() }
+    }
+
+    fn frames(messages: Vec<Message>) -> Vec<Value> {
+        messages.into_iter()
+            .map(|m| match m {
+                Message::Text(text) => serde_json::from_str(&text).unwrap(),
+                other => panic!("expected a text frame, got {:?}", other),
+            })
+            .collect()
+    }
+
+    fn metrics(slot: u64) -> EngineMetrics {
+        EngineMetrics { slot, epoch: 600, latency: 120, ..EngineMetrics::default() }
+    }
+
+    #[test]
+    fn subscribe_acks_then_sends_current_values() {
+        let mut conn = connection();
+        let replies = frames(conn.handle(r#"{"op":"subscribe","topics":["health","slot"]}"#, &metrics(10)));
+
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0], json!({ "type": "subscribed", "seq": 1, "data": { "topics": ["slot", "health"] } }));
+        assert_eq!(replies[1], json!({ "type": "update", "seq": 2, "topic": "slot", "data": { "slot": 10 } }));
+        assert_eq!((replies[2]["seq"].as_u64(), replies[2]["topic"].as_str()), (Some(3), Some("health")));
+        assert_eq!(replies[2]["data"]["latency_ms"], 120);
+
+        // Already subscribed: acknowledged, but no repeat of the current value.
+        let again = frames(conn.handle(r#"{"op":"subscribe","topics":["slot"]}"#, &metrics(10)));
+        assert_eq!(again, vec![json!({ "type": "subscribed", "seq": 4, "data": { "topics": ["slot", "health"] } })]);
+
+        let gone = frames(conn.handle(r#"{"op":"unsubscribe","topics":["slot","epoch"]}"#, &metrics(10)));
+        assert_eq!(gone, vec![json!({ "type": "unsubscribed", "seq": 5, "data": { "topics": ["health"] } })]);
+    }
+
+    #[test]
+    fn bad_frames_get_errors_and_change_nothing() {
+        let mut conn = connection();
+        let unknown = frames(conn.handle(r#"{"op":"subscribe","topics":["slot","mempool"]}"#, &metrics(10)));
+        assert_eq!(unknown.len(), 1);
+        assert_eq!((unknown[0]["type"].as_str(), unknown[0]["seq"].as_u64()), (Some("error"), Some(1)));
+        assert_eq!(unknown[0]["data"]["message"], "unknown topic 'mempool'");
+        assert!(conn.topics.is_empty());
+
+        let malformed = frames(conn.handle("subscribe slot", &metrics(10)));
+        assert_eq!((malformed[0]["type"].as_str(), malformed[0]["seq"].as_u64()), (Some("error"), Some(2)));
+    }
+
+    #[test]
+    fn updates_only_carry_changed_topics() {
+        let mut conn = connection();
+        conn.topics.extend([Topic::Slot, Topic::Epoch, Topic::Health]);
+        let prev = metrics(10);
+
+        assert!(conn.updates(&prev, &prev).is_empty());
+        assert_eq!(conn.seq, 0);
+
+        let next = frames(conn.updates(&prev, &metrics(11)));
+        assert_eq!(next, vec![json!({ "type": "update", "seq": 1, "topic": "slot", "data": { "slot": 11 } })]);
+
+        let degraded = EngineMetrics { status: EngineStatus::Degraded, ..prev.clone() };
+        let next = frames(conn.updates(&prev, &degraded));
+        assert_eq!(next.len(), 1);
+        assert_eq!((next[0]["seq"].as_u64(), next[0]["topic"].as_str()), (Some(2), Some("health")));
+        assert_eq!(next[0]["data"]["status"], "DEGRADED");
+
+        // Unsubscribed topics stay quiet even when they change.
+        conn.topics.insert(Topic::Metrics);
+        conn.topics.remove(&Topic::Slot);
+        let next = frames(conn.updates(&prev, &metrics(12)));
+        assert_eq!(next.iter().map(|f| f["topic"].as_str().unwrap()).collect::<Vec<_>>(), vec!["metrics"]);
+    }
+
+    async fn billed_key(credits: i64, expires_at: Option<DateTime<Utc>>) -> (Arc<AppState>, AuthedKey) {
+        config::init_for_tests();
+        let db = memory_pool().await;
+        let user_id: i64 = sqlx::query_scalar("INSERT INTO users (email, api_key, credits) VALUES (?, ?, ?) RETURNING id")
+            .bind("ws@example.com")
+            .bind(keys::mint().stored)
+            .bind(credits)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        let (_, row) = keys::create_key(&db, user_id, "ws", keys::DEFAULT_SCOPES, expires_at).await.unwrap();
+        let state = AppState::for_tests(db, Arc::new(ScriptedRpc::new("http://mock")));
+        (state, AuthedKey { user_id, api_key_id: Some(row.id) })
+    }
+
+    #[tokio::test]
+    async fn charge_bills_until_credits_run_out() {
+        let (state, key) = billed_key(1, None).await;
+        assert_eq!(charge(&state, &key).await, Ok(()));
+
+        let ledger: (String, i64, i64) = sqlx::query_as("SELECT endpoint, cost, status_code FROM usage_ledger WHERE user_id = ?")
+            .bind(key.user_id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+        assert_eq!(ledger, ("/api/v1/ws".to_string(), BILLING_COST, 101));
+
+        assert_eq!(charge(&state, &key).await, Err((CLOSE_CREDITS_EXHAUSTED, "credits exhausted")));
+        let credits: i64 = sqlx::query_scalar("SELECT credits FROM users WHERE id = ?")
+            .bind(key.user_id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+        assert_eq!(credits, 0);
+    }
+
+    #[tokio::test]
+    async fn charge_closes_for_rejected_keys() {
+        let (state, key) = billed_key(100, None).await;
+        sqlx::query("UPDATE api_keys SET revoked = 1 WHERE id = ?").bind(key.api_key_id).execute(&state.db).await.unwrap();
+        assert_eq!(charge(&state, &key).await, Err((CLOSE_KEY_REJECTED, "API key revoked")));
+
+        let deleted_user = AuthedKey { user_id: key.user_id + 1, api_key_id: None };
+        assert_eq!(charge(&state, &deleted_user).await, Err((CLOSE_KEY_REJECTED, "API key invalid")));
+
+        let (state, key) = billed_key(100, Some(Utc::now() - chrono::Duration::minutes(1))).await;
+        assert_eq!(charge(&state, &key).await, Err((CLOSE_KEY_REJECTED, "API key expired")));
+    }
+}
@@ -0,0 +1,175 @@
+//! End-to-end: the server binary against local JSON-RPC stand-ins.
+
+mod support;
+
+use serde_json::{json, Value};
+use solana_sdk::signature::{Signature, Signer};
+use solana_sdk::pubkey::Pubkey;
+use std::time::Duration;
+use support::standin::{Scenario, StandIn};
+use support::App;
+
+const SETTLE: Duration = Duration::from_secs(15);
+
+#[tokio::test]
+async fn steady_node_reports_operational_metrics() {
+    let node = StandIn::start(Scenario::Steady).await;
+    let app = App::start(&[node.url()]).await;
+
+    let m = app.wait_for_metrics("operational over websocket", SETTLE, |m| {
+        m["status"] == "OPERATIONAL" && m["slot_source"] == "websocket"
+    })
+    .await;
+    assert!(m["slot"].as_u64().unwrap() > support::standin::FIRST_SLOT);
+    assert_eq!(m["epoch"], json!(node.slot() / 432_000));
+    assert_eq!(m["tps"], json!(3_000));
+    assert_eq!(m["tps_method"], "performance_samples");
+    assert_eq!(m["forked_nodes"], json!([]));
+
+    let first = m["slot"].as_u64().unwrap();
+    app.wait_for_metrics("slot to advance", SETTLE, |m| m["slot"].as_u64().unwrap() > first).await;
+
+    let m = app.wait_for_metrics("average slot time", SETTLE, |m| !m["slot_time_ms"].is_null()).await;
+    let slot_time = m["slot_time_ms"].as_u64().unwrap();
+    assert!((300..=600).contains(&slot_time), "slot time {}ms, stand-in produces one per 400ms", slot_time);
+    assert_eq!(m["skip_rate"], json!(0.0));
+}
+
+#[tokio::test]
+async fn stream_pushes_metrics_events() {
+    let node = StandIn::start(Scenario::Steady).await;
+    let app = App::start(&[node.url()]).await;
+    let key = app.api_key().await;
+
+    let mut stream = app.stream(&key).await;
+    let first = stream.next(SETTLE).await;
+    assert_eq!(first.event, "metrics");
+    // Same body as `/api/metrics`.
+    assert!(first.data["data"]["latency_ms"].is_u64());
+    let first_id: u64 = first.id.as_deref().unwrap().parse().unwrap();
+
+    // Slots land every 400ms, so updates keep coming with increasing ids and slots.
+    let mut last = (first_id, first.data["data"]["slot"].as_u64().unwrap());
+    for _ in 0..5 {
+        let e = stream.next(SETTLE).await;
+        assert_eq!(e.event, "metrics");
+        let id: u64 = e.id.as_deref().unwrap().parse().unwrap();
+        let slot = e.data["data"]["slot"].as_u64().unwrap();
+        assert!(id > last.0, "event ids must increase");
+        assert!(slot >= last.1, "slot went backwards");
+        last = (id, slot);
+    }
+    assert!(last.1 > first.data["data"]["slot"].as_u64().unwrap());
+}
+
+#[tokio::test]
+async fn errors_take_status_down_and_back() {
+    let node = StandIn::start(Scenario::Steady).await;
+    let app = App::start_with(&[node.url()], &[("ARKHEION_DOWN_AFTER_ERRORS", "10")]).await;
+    app.wait_for_metrics("operational", SETTLE, |m| m["status"] == "OPERATIONAL").await;
+
+    node.set_scenario(Scenario::Errors);
+    let m = app.wait_for_metrics("down", SETTLE, |m| m["status"] == "DOWN").await;
+    assert_eq!(m["status_reason"], "rpc_errors");
+    assert!(m["consecutive_errors"].as_u64().unwrap() >= 10);
+
+    node.set_scenario(Scenario::Steady);
+    let m = app.wait_for_metrics("operational again", SETTLE, |m| m["status"] == "OPERATIONAL").await;
+    assert_eq!(m["consecutive_errors"], 0);
+
+    let status = app.get_json("/api/v1/status").await["data"].clone();
+    assert_eq!(status["status"], "OPERATIONAL");
+    assert_eq!(status["reason"], "healthy");
+    let path: Vec<&str> = status["transitions"].as_array().unwrap().iter().map(|t| t["to"].as_str().unwrap()).collect();
+    assert_eq!(path, ["OPERATIONAL", "RECONNECTING", "DOWN", "OPERATIONAL"]);
+}
+
+#[tokio::test]
+async fn stalled_node_degrades_and_recovers() {
+    let node = StandIn::start(Scenario::Steady).await;
+    let app = App::start_with(&[node.url()], &[("ARKHEION_STALL_AFTER_SECS", "2")]).await;
+    app.wait_for_metrics("operational", SETTLE, |m| m["status"] == "OPERATIONAL").await;
+
+    node.set_scenario(Scenario::Stalled);
+    let stalled_at = node.slot();
+    app.wait_for_metrics("slot to catch up with the stall", SETTLE, |m| m["slot"] == json!(stalled_at)).await;
+    let m = app.wait_for_metrics("degraded", SETTLE, |m| m["status"] == "DEGRADED").await;
+    assert_eq!(m["status_reason"], "slot_stall");
+    assert_eq!(m["slot"], json!(stalled_at));
+
+    node.set_scenario(Scenario::Steady);
+    let m = app.wait_for_metrics("operational again", SETTLE, |m| m["status"] == "OPERATIONAL").await;
+    assert!(m["slot"].as_u64().unwrap() > stalled_at);
+}
+
+#[tokio::test]
+async fn high_skip_rate_degrades() {
+    let node = StandIn::start(Scenario::Steady).await;
+    node.set_skip_every(4);
+    let app = App::start_with(&[node.url()], &[("ARKHEION_DEGRADED_SKIP_RATE", "0.2")]).await;
+
+    let m = app.wait_for_metrics("degraded", SETTLE, |m| m["status"] == "DEGRADED").await;
+    assert_eq!(m["status_reason"], "high_skip_rate");
+    assert_eq!(m["skip_rate"], json!(0.25));
+}
+
+#[tokio::test]
+async fn forked_node_is_reported() {
+    let (a, b, forked) = (
+        StandIn::start(Scenario::Steady).await,
+        StandIn::start(Scenario::Steady).await,
+        StandIn::start(Scenario::Forked).await,
+    );
+    let app = App::start(&[a.url(), b.url(), forked.url()]).await;
+
+    let m = app.wait_for_metrics("fork detection", SETTLE, |m| m["forked_nodes"] != json!([])).await;
+    assert_eq!(m["forked_nodes"], json!([forked.url()]));
+    assert_eq!(m["status"], "OPERATIONAL");
+
+    forked.set_scenario(Scenario::Steady);
+    app.wait_for_metrics("fork resolved", SETTLE, |m| m["forked_nodes"] == json!([])).await;
+}
+
+#[tokio::test]
+async fn payment_is_verified_against_the_node() {
+    let node = StandIn::start(Scenario::Steady).await;
+    let treasury = Pubkey::new_unique().to_string();
+    let app = App::start_with(&[node.url()], &[("ARKHEION_TREASURY", &treasury)]).await;
+    let (wallet, session) = app.sign_in().await;
+
+    // First account in a fresh database.
+    let memo = "arkheion:1:credits_10k";
+    let signature = Signature::new_unique().to_string();
+    node.add_transaction(&signature, transfer(&signature, &wallet.pubkey().to_string(), &treasury, 50_000_000, memo));
+
+    let res = app.post_json("/api/v1/billing/payments", Some(&session), json!({ "signature": signature, "plan": "credits_10k" })).await;
+    assert_eq!(res["result"]["status"], "confirmed");
+    assert_eq!(res["result"]["credits_added"], 10_000);
+}
+
+/// A jsonParsed `getTransaction` result for a system transfer with a memo.
+fn transfer(signature: &str, from: &str, to: &str, lamports: u64, memo: &str) -> Value {
+    json!({
+        "slot": 250_000_000u64,
+        "blockTime": 1_700_000_000,
+        "meta": null,
+        "transaction": {
+            "signatures": [signature],
+            "message": {
+                "accountKeys": [
+                    { "pubkey": from, "writable": true, "signer": true, "source": "transaction" },
+                    { "pubkey": to, "writable": true, "signer": false, "source": "transaction" },
+                ],
+                "recentBlockhash": "11111111111111111111111111111111",
+                "instructions": [
+                    {
+                        "program": "system",
+                        "programId": "11111111111111111111111111111111",
+                        "parsed": { "type": "transfer", "info": { "source": from, "destination": to, "lamports": lamports } },
+                    },
+                    { "program": "spl-memo", "programId": "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr", "parsed": memo },
+                ],
+            },
+        },
+    })
+}
@@ -0,0 +1,205 @@
+//! Boots the real server binary against [`standin::StandIn`] nodes and talks to it over HTTP.
+
+#![allow(dead_code)]
+
+pub mod standin;
+
+use serde_json::{json, Value};
+use solana_sdk::signature::{Keypair, Signer};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+pub const API_KEY_SECRET: &str = "e2e-test-secret-e2e-test-secret-e2e";
+/// Anonymous callers get 2 requests/s; polling slower than that never hits a 429.
+const POLL_EVERY: Duration = Duration::from_millis(600);
+
+/// The server binary, in a scratch directory so no `arkheion.toml` or `.env` is picked up.
+pub struct App {
+    pub addr: SocketAddr,
+    pub client: reqwest::Client,
+    dir: PathBuf,
+    child: Child,
+}
+
+impl App {
+    pub async fn start(rpc_urls: &[String]) -> Self {
+        Self::start_with(rpc_urls, &[]).await
+    }
+
+    /// `env` adds or overrides `ARKHEION_*` variables.
+    pub async fn start_with(rpc_urls: &[String], env: &[(&str, &str)]) -> Self {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let dir = std::env::temp_dir().join(format!("arkheion-e2e-{}-{}", std::process::id(), port));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_arkheion_engine"));
+        cmd.current_dir(&dir)
+            .env("ARKHEION_PORT", port.to_string())
+            .env("ARKHEION_RPC_URLS", rpc_urls.join(","))
+            .env("ARKHEION_RPC_TIMEOUT_MS", "2000")
+            .env("ARKHEION_POLL_INTERVAL_MS", "200")
+            .env("ARKHEION_PROBE_INTERVAL_SECS", "1")
+            .env("ARKHEION_DATABASE_URL", format!("sqlite://{}/e2e.db?mode=rwc", dir.display()))
+            .env("ARKHEION_API_KEY_SECRET", API_KEY_SECRET)
+            .env("ARKHEION_LOG_FILTER", std::env::var("E2E_LOG").unwrap_or_else(|_| "error".to_string()))
+            .stdout(Stdio::null());
+        for (key, value) in env {
+            cmd.env(key, value);
+        }
+        let child = cmd.spawn().expect("failed to start arkheion_engine");
+
+        let app = Self { addr: SocketAddr::from(([127, 0, 0, 1], port)), client: reqwest::Client::new(), dir, child };
+        app.wait_ready().await;
+        app
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    async fn wait_ready(&self) {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while Instant::now() < deadline {
+            if let Ok(res) = self.client.get(self.url("/")).send().await {
+                if res.status().is_success() {
+                    return;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("server did not come up on {}", self.addr);
+    }
+
+    /// `data` of `/api/metrics`.
+    pub async fn metrics(&self) -> Value {
+        self.get_json("/api/metrics").await["data"].clone()
+    }
+
+    pub async fn get_json(&self, path: &str) -> Value {
+        let res = self.client.get(self.url(path)).send().await.unwrap();
+        assert!(res.status().is_success(), "{} answered {}", path, res.status());
+        serde_json::from_str(&res.text().await.unwrap()).unwrap()
+    }
+
+    /// Polls `/api/metrics` until `pred` holds and returns that snapshot.
+    pub async fn wait_for_metrics(&self, what: &str, timeout: Duration, pred: impl Fn(&Value) -> bool) -> Value {
+        let deadline = Instant::now() + timeout;
+        let mut last = Value::Null;
+        while Instant::now() < deadline {
+            last = self.metrics().await;
+            if pred(&last) {
+                return last;
+            }
+            tokio::time::sleep(POLL_EVERY).await;
+        }
+        panic!("timed out waiting for {}; last metrics: {}", what, last);
+    }
+
+    /// Signs in with a fresh wallet. Returns the wallet and the session token.
+    pub async fn sign_in(&self) -> (Keypair, String) {
+        let wallet = Keypair::new();
+        let public_key = wallet.pubkey().to_string();
+
+        let nonce = self.post_json("/api/v1/auth/nonce", None, json!({ "public_key": public_key })).await;
+        let signature = wallet.sign_message(nonce["message"].as_str().unwrap().as_bytes());
+        let verified = self
+            .post_json("/api/v1/auth/verify", None, json!({
+                "public_key": public_key,
+                "nonce": nonce["nonce"],
+                "signature": signature.to_string(),
+            }))
+            .await;
+        (wallet, verified["session"].as_str().unwrap().to_string())
+    }
+
+    /// Signs in and mints an API key with the default scopes.
+    pub async fn api_key(&self) -> String {
+        let (_, session) = self.sign_in().await;
+        let created = self.post_json("/api/v1/keys", Some(&session), json!({ "label": "e2e" })).await;
+        created["key"].as_str().unwrap().to_string()
+    }
+
+    pub async fn post_json(&self, path: &str, session: Option<&str>, body: Value) -> Value {
+        let mut req = self.client.post(self.url(path))
+            .header("content-type", "application/json")
+            .body(body.to_string());
+        if let Some(session) = session {
+            req = req.header("authorization", format!("Session {}", session));
+        }
+        let res = req.send().await.unwrap();
+        let status = res.status();
+        let text = res.text().await.unwrap();
+        assert!(status.is_success(), "POST {} answered {}: {}", path, status, text);
+        serde_json::from_str(&text).unwrap()
+    }
+
+    /// Opens `/api/v1/stream` with `key`.
+    pub async fn stream(&self, key: &str) -> SseStream {
+        let res = self.client.get(self.url("/api/v1/stream"))
+            .header("authorization", format!("Bearer {}", key))
+            .send()
+            .await
+            .unwrap();
+        assert!(res.status().is_success(), "/api/v1/stream answered {}", res.status());
+        assert_eq!(res.headers()["content-type"], "text/event-stream");
+        SseStream { res, buf: String::new() }
+    }
+}
+
+impl Drop for App {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[derive(Debug)]
+pub struct SseEvent {
+    pub event: String,
+    pub id: Option<String>,
+    pub data: Value,
+}
+
+pub struct SseStream {
+    res: reqwest::Response,
+    buf: String,
+}
+
+impl SseStream {
+    /// The next event with data, skipping comments and heartbeats.
+    pub async fn next(&mut self, timeout: Duration) -> SseEvent {
+        tokio::time::timeout(timeout, async {
+            loop {
+                if let Some(end) = self.buf.find("\n\n") {
+                    let block: String = self.buf.drain(..end + 2).collect();
+                    if let Some(event) = parse_event(&block) {
+                        return event;
+                    }
+                    continue;
+                }
+                let chunk = self.res.chunk().await.unwrap().expect("stream ended");
+                self.buf.push_str(&String::from_utf8_lossy(&chunk));
+            }
+        })
+        .await
+        .expect("no SSE event in time")
+    }
+}
+
+fn parse_event(block: &str) -> Option<SseEvent> {
+    let (mut event, mut id, mut data) = ("message".to_string(), None, String::new());
+    for line in block.lines() {
+        if let Some(v) = line.strip_prefix("event:") {
+            event = v.trim().to_string();
+        } else if let Some(v) = line.strip_prefix("id:") {
+            id = Some(v.trim().to_string());
+        } else if let Some(v) = line.strip_prefix("data:") {
+            data.push_str(v.trim_start());
+        }
+    }
+    let data = serde_json::from_str(&data).ok()?;
+    Some(SseEvent { event, id, data })
+}
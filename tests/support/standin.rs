@@ -0,0 +1,254 @@
+//! A local stand-in for a Solana RPC node: JSON-RPC over HTTP plus `slotSubscribe` over
+//! WebSocket, answering from a scripted [`Scenario`] that tests can switch at runtime.
+//!
+//! Like a hosted provider, both are served on the same port.
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener as StdListener};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+pub const SLOT_TIME: Duration = Duration::from_millis(400);
+pub const FIRST_SLOT: u64 = 250_000_000;
+const SLOTS_PER_EPOCH: u64 = 432_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    /// A new slot every [`SLOT_TIME`]; every method answers.
+    Steady,
+    /// The slot stops advancing and no notifications are sent; calls still answer.
+    Stalled,
+    /// Every request fails with a JSON-RPC internal error and notifications stop.
+    Errors,
+    /// Like `Steady`, but every block hash differs from what other nodes report.
+    Forked,
+}
+
+struct Node {
+    scenario: Scenario,
+    slot: u64,
+    /// Answer every request only after this long.
+    delay: Duration,
+    /// Every slot divisible by this has no block.
+    skip_every: Option<u64>,
+    /// signature → (`getSignatureStatuses` entry, `getTransaction` result)
+    transactions: HashMap<String, (Value, Value)>,
+}
+
+struct Shared {
+    node: Mutex<Node>,
+    slots: broadcast::Sender<u64>,
+}
+
+pub struct StandIn {
+    pub addr: SocketAddr,
+    shared: Arc<Shared>,
+}
+
+impl StandIn {
+    pub async fn start(scenario: Scenario) -> Self {
+        let listener = StdListener::bind("127.0.0.1:0").unwrap();
+        let (slots, _) = broadcast::channel(64);
+        let node = Node { scenario, slot: FIRST_SLOT, delay: Duration::ZERO, skip_every: None, transactions: HashMap::new() };
+        let shared = Arc::new(Shared { node: Mutex::new(node), slots });
+
+        let app = Router::new().route("/", post(rpc).get(pubsub_upgrade)).with_state(shared.clone());
+        let addr = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        tokio::spawn(produce_slots(shared.clone()));
+
+        Self { addr, shared }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn set_scenario(&self, scenario: Scenario) {
+        self.shared.node.lock().unwrap().scenario = scenario;
+    }
+
+    pub fn set_delay(&self, delay: Duration) {
+        self.shared.node.lock().unwrap().delay = delay;
+    }
+
+    /// Leaves every `n`th slot without a block, past and future, for a skip rate of 1/`n`.
+    pub fn set_skip_every(&self, n: u64) {
+        self.shared.node.lock().unwrap().skip_every = Some(n);
+    }
+
+    pub fn slot(&self) -> u64 {
+        self.shared.node.lock().unwrap().slot
+    }
+
+    /// Makes `signature` known as a finalized transaction whose `getTransaction` result
+    /// is `transaction` (jsonParsed).
+    pub fn add_transaction(&self, signature: &str, transaction: Value) {
+        let mut node = self.shared.node.lock().unwrap();
+        let status = json!({
+            "slot": node.slot,
+            "confirmations": null,
+            "status": { "Ok": null },
+            "err": null,
+            "confirmationStatus": "finalized",
+        });
+        node.transactions.insert(signature.to_string(), (status, transaction));
+    }
+}
+
+async fn produce_slots(shared: Arc<Shared>) {
+    let mut tick = tokio::time::interval(SLOT_TIME);
+    loop {
+        tick.tick().await;
+        let slot = {
+            let mut node = shared.node.lock().unwrap();
+            match node.scenario {
+                Scenario::Steady | Scenario::Forked => {
+                    node.slot += 1;
+                    node.slot
+                }
+                Scenario::Stalled | Scenario::Errors => continue,
+            }
+        };
+        let _ = shared.slots.send(slot);
+    }
+}
+
+impl Node {
+    fn skipped(&self, slot: u64) -> bool {
+        self.skip_every.is_some_and(|n| slot % n == 0)
+    }
+}
+
+fn block_hash(slot: u64, forked: bool) -> String {
+    let seed = if forked { format!("fork-{}", slot) } else { format!("block-{}", slot) };
+    bs58::encode(seed).into_string()
+}
+
+async fn rpc(State(shared): State<Arc<Shared>>, Json(req): Json<Value>) -> Json<Value> {
+    let delay = shared.node.lock().unwrap().delay;
+    tokio::time::sleep(delay).await;
+
+    let id = req["id"].clone();
+    let params = &req["params"];
+    let node = shared.node.lock().unwrap();
+    if node.scenario == Scenario::Errors {
+        return Json(json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32603, "message": "Internal error" } }));
+    }
+
+    let slot = node.slot;
+    let result = match req["method"].as_str().unwrap_or("") {
+        "getSlot" => json!(slot),
+        "getEpochInfo" => json!({
+            "absoluteSlot": slot,
+            "blockHeight": slot - 20_000_000,
+            "epoch": slot / SLOTS_PER_EPOCH,
+            "slotIndex": slot % SLOTS_PER_EPOCH,
+            "slotsInEpoch": SLOTS_PER_EPOCH,
+            "transactionCount": slot * 1_200,
+        }),
+        "getRecentPerformanceSamples" => json!([{
+            "slot": slot,
+            "numSlots": 150,
+            "numTransactions": 180_000,
+            "numNonVoteTransactions": 45_000,
+            "samplePeriodSecs": 60,
+        }]),
+        "getBlocksWithLimit" => {
+            let start = params[0].as_u64().unwrap_or(slot);
+            let limit = params[1].as_u64().unwrap_or(1);
+            json!((start..=slot).filter(|s| !node.skipped(*s)).take(limit as usize).collect::<Vec<u64>>())
+        }
+        "getBlocks" => {
+            let start = params[0].as_u64().unwrap_or(slot);
+            let end = params[1].as_u64().unwrap_or(slot).min(slot);
+            json!((start..=end).filter(|s| !node.skipped(*s)).collect::<Vec<u64>>())
+        }
+        "getBlock" => {
+            let at = params[0].as_u64().unwrap_or(slot);
+            if node.skipped(at) {
+                let message = format!("Slot {} was skipped, or missing due to ledger jump to recent snapshot", at);
+                return Json(json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32007, "message": message } }));
+            }
+            json!({
+                "blockhash": block_hash(at, node.scenario == Scenario::Forked),
+                "previousBlockhash": block_hash(at - 1, node.scenario == Scenario::Forked),
+                "parentSlot": at - 1,
+                "blockHeight": at - 20_000_000,
+                "blockTime": 1_700_000_000,
+            })
+        }
+        "getSignatureStatuses" => {
+            let statuses: Vec<Value> = params[0].as_array().into_iter().flatten()
+                .map(|sig| sig.as_str().and_then(|s| node.transactions.get(s)).map_or(Value::Null, |(status, _)| status.clone()))
+                .collect();
+            json!({ "context": { "slot": slot }, "value": statuses })
+        }
+        "getTransaction" => params[0].as_str()
+            .and_then(|s| node.transactions.get(s))
+            .map_or(Value::Null, |(_, tx)| tx.clone()),
+        method => {
+            let message = format!("Method not found: {}", method);
+            return Json(json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32601, "message": message } }));
+        }
+    };
+    Json(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+}
+
+async fn pubsub_upgrade(State(shared): State<Arc<Shared>>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| pubsub(socket, shared)).into_response()
+}
+
+/// Just enough of the PubSub protocol for `slotSubscribe`.
+async fn pubsub(mut socket: WebSocket, shared: Arc<Shared>) {
+    let mut slots = shared.slots.subscribe();
+    let mut subscription: Option<u64> = None;
+
+    loop {
+        tokio::select! {
+            msg = socket.recv() => {
+                let Some(Ok(Message::Text(text))) = msg else { return };
+                let Ok(req) = serde_json::from_str::<Value>(&text) else { continue };
+                let reply = match req["method"].as_str() {
+                    Some("slotSubscribe") => {
+                        subscription = Some(1);
+                        json!({ "jsonrpc": "2.0", "id": req["id"], "result": 1 })
+                    }
+                    Some("slotUnsubscribe") => {
+                        subscription = None;
+                        json!({ "jsonrpc": "2.0", "id": req["id"], "result": true })
+                    }
+                    _ => json!({ "jsonrpc": "2.0", "id": req["id"], "error": { "code": -32601, "message": "Method not found" } }),
+                };
+                if socket.send(Message::Text(reply.to_string())).await.is_err() {
+                    return;
+                }
+            }
+            slot = slots.recv() => {
+                let Ok(slot) = slot else { continue };
+                let Some(id) = subscription else { continue };
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "slotNotification",
+                    "params": { "result": { "parent": slot - 1, "root": slot - 32, "slot": slot }, "subscription": id },
+                });
+                if socket.send(Message::Text(notification.to_string())).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}